@@ -5,11 +5,23 @@ use fatcat_openapi::client::Client;
 use fatcat_openapi::models;
 use swagger::{AuthData, ContextBuilder, EmptyContext, Push, XSpanIdString, auth};
 use anyhow::{Result, anyhow, Context};
-use crate::{ClientStatus,parse_macaroon_editor_id,Specifier, EntityType};
+use crate::{ApiEntityModel, ApiModelSer, ClientStatus, entity_content,parse_macaroon_editor_id,parse_macaroon_token,Specifier, EntityType};
 use tokio::runtime::current_thread::Runtime;
 
 type FatcatApiContextType = swagger::make_context_ty!( ContextBuilder, EmptyContext, Option<AuthData>, XSpanIdString);
 
+/// Outcome of `FatcatApiClient::update_entity_checked()`
+#[derive(Debug, PartialEq, Clone)]
+pub enum UpdateResult {
+    Updated(models::EntityEdit),
+    Unchanged(Specifier),
+    Conflict {
+        specifier: Specifier,
+        expected_revision: String,
+        current_revision: String,
+    },
+}
+
 pub struct FatcatApiClient<'a> {
     pub api: fatcat_openapi::ContextWrapper<'a, Client<ResponseFuture>, FatcatApiContextType>,
     pub rt: tokio::runtime::current_thread::Runtime,
@@ -57,7 +69,13 @@ impl<'a> FatcatApiClient<'a> {
             Ok(_) | Err(_) => None,
         };
         let has_api_token = self.api_token.is_some();
-        let account: Option<models::Editor> = if has_api_token && last_changelog.is_some() {
+        let token_info = match self.api_token {
+            Some(ref token) => Some(parse_macaroon_token(token)?),
+            None => None,
+        };
+        let token_expired = token_info.as_ref().map_or(false, |info| info.is_expired());
+        // an expired token would fail the auth check; status reports the expiry instead
+        let account: Option<models::Editor> = if has_api_token && last_changelog.is_some() && !token_expired {
             match self.rt.block_on(self.api.auth_check(None)).context("check auth token")? {
                 fatcat_openapi::AuthCheckResponse::Success(_) => Ok(()),
                 fatcat_openapi::AuthCheckResponse::Forbidden(err) => Err(anyhow!("Forbidden ({}): {}", err.error, err.message)),
@@ -77,18 +95,126 @@ impl<'a> FatcatApiClient<'a> {
             has_api_token,
             last_changelog,
             account,
+            current_editgroup: None,
+            token_expires: token_info.and_then(|info| info.expires),
         })
     }
 
-    pub fn update_editgroup_submit(&mut self, editgroup_id: String, submit: bool) -> Result<models::Editgroup> {
+    /// Mints a new API token for the given editor; requires admin privileges.
+    pub fn create_auth_token(&mut self, editor_id: String, duration_seconds: Option<i32>) -> Result<String> {
+        let result = self.rt.block_on(
+            self.api.create_auth_token(editor_id.clone(), duration_seconds)
+        ).context("create auth token")?;
+        match result {
+            fatcat_openapi::CreateAuthTokenResponse::Success(result) => Ok(result.token),
+            fatcat_openapi::CreateAuthTokenResponse::Forbidden(err) => Err(anyhow!("Forbidden ({}): {}", err.error, err.message)),
+            fatcat_openapi::CreateAuthTokenResponse::NotAuthorized{body: err, ..} => Err(anyhow!("Not Authorized ({}): {}", err.error, err.message)),
+            other => Err(anyhow!("{:?}", other)),
+        }.with_context(|| format!("failed to create auth token for editor_{}", editor_id))
+    }
+
+    pub fn create_editgroup(&mut self, description: Option<String>) -> Result<models::Editgroup> {
+        let eg = new_editgroup_model(description);
+        match self.rt.block_on(self.api.create_editgroup(eg)).context("create editgroup")? {
+            fatcat_openapi::CreateEditgroupResponse::SuccessfullyCreated(eg) => Ok(eg),
+            other => Err(anyhow!("{:?}", other)).context("failed to create editgroup"),
+        }
+    }
+
+    pub fn get_editor_editgroups(&mut self, editor_id: String, limit: Option<i64>) -> Result<Vec<models::Editgroup>> {
+        let result = self.rt.block_on(
+            self.api.get_editor_editgroups(editor_id.clone(), limit, None, None)
+        ).context("fetch editgroups")?;
+        match result {
+            fatcat_openapi::GetEditorEditgroupsResponse::Found(eg_list) => Ok(eg_list),
+            other => Err(anyhow!("{:?}", other))
+                .with_context(|| format!("failed to fetch editgroups for editor_{}", editor_id)),
+        }
+    }
+
+    /// Finds the most recently created editgroup of the authenticated editor which was created by
+    /// fatcat-cli and has not been submitted or accepted yet.
+    pub fn find_unsubmitted_editgroup(&mut self) -> Result<Option<models::Editgroup>> {
+        let editor_id = match self.editor_id {
+            Some(ref eid) => eid.clone(),
+            None => return Err(anyhow!("require a working auth token to find editgroups")),
+        };
+        Ok(self.get_editor_editgroups(editor_id, Some(50))?.into_iter()
+            .filter(|eg| eg.changelog_index.is_none() && eg.submitted.is_none())
+            .filter(|eg| match eg.extra {
+                Some(ref extra) => extra.get("agent") == Some(&serde_json::Value::String("fatcat-cli".to_string())),
+                None => false,
+            })
+            .max_by_key(|eg| eg.created))
+    }
+
+    /// Fetches the most recent changelog entries, in the order returned by the API (most recent
+    /// first).
+    pub fn get_changelog(&mut self, limit: Option<i64>) -> Result<Vec<models::ChangelogEntry>> {
+        match self.rt.block_on(self.api.get_changelog(limit)).context("fetch changelog")? {
+            fatcat_openapi::GetChangelogResponse::Success(entry_list) => Ok(entry_list),
+            fatcat_openapi::GetChangelogResponse::BadRequest(err) => Err(anyhow!("Bad Request ({}): {}", err.error, err.message)),
+            other => Err(anyhow!("{:?}", other)).context("failed to fetch changelog"),
+        }
+    }
+
+    pub fn get_changelog_entry(&mut self, index: i64) -> Result<models::ChangelogEntry> {
+        match self.rt.block_on(self.api.get_changelog_entry(index)).context("fetch changelog entry")? {
+            fatcat_openapi::GetChangelogEntryResponse::FoundChangelogEntry(entry) => Ok(entry),
+            fatcat_openapi::GetChangelogEntryResponse::NotFound(err) => Err(anyhow!("Not Found: {}", err.message)),
+            other => Err(anyhow!("{:?}", other)),
+        }.with_context(|| format!("failed to fetch changelog_{}", index))
+    }
+
+    pub fn get_editgroup(&mut self, editgroup_id: String) -> Result<models::Editgroup> {
         let result = self.rt.block_on(
             self.api.get_editgroup(editgroup_id.clone())
         ).context("fetch editgroups")?;
-        let eg = match result {
-            fatcat_openapi::GetEditgroupResponse::Found(eg) => eg,
-            other => return Err(anyhow!("{:?}", other))
+        match result {
+            fatcat_openapi::GetEditgroupResponse::Found(eg) => Ok(eg),
+            other => Err(anyhow!("{:?}", other))
                 .with_context(|| format!("failed to fetch editgroup {}", editgroup_id)),
-        };
+        }
+    }
+
+    pub fn create_editgroup_annotation(&mut self, editgroup_id: String, comment: String, extra: Option<std::collections::HashMap<String, serde_json::Value>>) -> Result<models::EditgroupAnnotation> {
+        let mut annotation = models::EditgroupAnnotation::new();
+        annotation.comment_markdown = Some(comment);
+        annotation.extra = extra;
+        let result = self.rt.block_on(
+            self.api.create_editgroup_annotation(editgroup_id.clone(), annotation)
+        ).context("create editgroup annotation")?;
+        match result {
+            fatcat_openapi::CreateEditgroupAnnotationResponse::Created(annotation) => Ok(annotation),
+            other => Err(anyhow!("{:?}", other))
+                .with_context(|| format!("failed to annotate editgroup {}", editgroup_id)),
+        }
+    }
+
+    pub fn get_editgroup_annotations(&mut self, editgroup_id: String) -> Result<Vec<models::EditgroupAnnotation>> {
+        let result = self.rt.block_on(
+            self.api.get_editgroup_annotations(editgroup_id.clone(), Some("editors".to_string()))
+        ).context("fetch editgroup annotations")?;
+        match result {
+            fatcat_openapi::GetEditgroupAnnotationsResponse::Success(annotation_list) => Ok(annotation_list),
+            other => Err(anyhow!("{:?}", other))
+                .with_context(|| format!("failed to fetch annotations for editgroup {}", editgroup_id)),
+        }
+    }
+
+    pub fn get_editor_annotations(&mut self, editor_id: String, limit: Option<i64>) -> Result<Vec<models::EditgroupAnnotation>> {
+        let result = self.rt.block_on(
+            self.api.get_editor_annotations(editor_id.clone(), limit, None, None)
+        ).context("fetch editor annotations")?;
+        match result {
+            fatcat_openapi::GetEditorAnnotationsResponse::Success(annotation_list) => Ok(annotation_list),
+            other => Err(anyhow!("{:?}", other))
+                .with_context(|| format!("failed to fetch annotations for editor_{}", editor_id)),
+        }
+    }
+
+    pub fn update_editgroup_submit(&mut self, editgroup_id: String, submit: bool) -> Result<models::Editgroup> {
+        let eg = self.get_editgroup(editgroup_id.clone())?;
         let result = self.rt.block_on(
             self.api.update_editgroup(editgroup_id.clone(), eg, Some(submit))
         ).context("submit editgroup")?;
@@ -99,10 +225,84 @@ impl<'a> FatcatApiClient<'a> {
         }
     }
 
+    /// Finds an existing edit of the given entity in an editgroup, if there is one. Only ident
+    /// specifiers (not lookups or revisions) can match an edit.
+    pub fn find_existing_edit(&mut self, specifier: &Specifier, editgroup_id: &str) -> Result<Option<models::EntityEdit>> {
+        use Specifier::*;
+        match specifier {
+            Release(_) | Work(_) | Container(_) | Creator(_) | File(_) | FileSet(_) | WebCapture(_) => (),
+            _ => return Ok(None),
+        };
+        let eg = self.get_editgroup(editgroup_id.to_string())?;
+        Ok(match eg.edits {
+            Some(edits) => find_edit(&edits, specifier),
+            None => None,
+        })
+    }
+
+    /// Fetches the version of an entity that a new edit in the editgroup should start from: the
+    /// revision of any existing edit of the entity in the editgroup, otherwise the current version.
+    pub fn get_entity_in_editgroup(&mut self, specifier: &Specifier, editgroup_id: &str) -> Result<Box<dyn ApiEntityModel>> {
+        let existing_edit = self.find_existing_edit(specifier, editgroup_id)?;
+        self.get_entity_with_edit(specifier, &existing_edit)
+    }
+
+    /// Like `get_entity_in_editgroup()`, with the existing edit (if any) already known
+    pub fn get_entity_with_edit(&mut self, specifier: &Specifier, existing_edit: &Option<models::EntityEdit>) -> Result<Box<dyn ApiEntityModel>> {
+        edit_base_specifier(specifier, existing_edit).get_from_api(self, None, None)
+    }
+
+    pub fn delete_entity_edit(&mut self, entity_type: EntityType, editgroup_id: &str, edit_id: &str) -> Result<()> {
+        match entity_type {
+            EntityType::Release => match self.rt.block_on(self.api.delete_release_edit(editgroup_id.to_string(), edit_id.to_string()))? {
+                fatcat_openapi::DeleteReleaseEditResponse::DeletedEdit(_) => Ok(()),
+                other => Err(anyhow!("{:?}", other)),
+            },
+            EntityType::Work => match self.rt.block_on(self.api.delete_work_edit(editgroup_id.to_string(), edit_id.to_string()))? {
+                fatcat_openapi::DeleteWorkEditResponse::DeletedEdit(_) => Ok(()),
+                other => Err(anyhow!("{:?}", other)),
+            },
+            EntityType::Container => match self.rt.block_on(self.api.delete_container_edit(editgroup_id.to_string(), edit_id.to_string()))? {
+                fatcat_openapi::DeleteContainerEditResponse::DeletedEdit(_) => Ok(()),
+                other => Err(anyhow!("{:?}", other)),
+            },
+            EntityType::Creator => match self.rt.block_on(self.api.delete_creator_edit(editgroup_id.to_string(), edit_id.to_string()))? {
+                fatcat_openapi::DeleteCreatorEditResponse::DeletedEdit(_) => Ok(()),
+                other => Err(anyhow!("{:?}", other)),
+            },
+            EntityType::File => match self.rt.block_on(self.api.delete_file_edit(editgroup_id.to_string(), edit_id.to_string()))? {
+                fatcat_openapi::DeleteFileEditResponse::DeletedEdit(_) => Ok(()),
+                other => Err(anyhow!("{:?}", other)),
+            },
+            EntityType::FileSet => match self.rt.block_on(self.api.delete_fileset_edit(editgroup_id.to_string(), edit_id.to_string()))? {
+                fatcat_openapi::DeleteFilesetEditResponse::DeletedEdit(_) => Ok(()),
+                other => Err(anyhow!("{:?}", other)),
+            },
+            EntityType::WebCapture => match self.rt.block_on(self.api.delete_webcapture_edit(editgroup_id.to_string(), edit_id.to_string()))? {
+                fatcat_openapi::DeleteWebcaptureEditResponse::DeletedEdit(_) => Ok(()),
+                other => Err(anyhow!("{:?}", other)),
+            },
+        }.with_context(|| format!("failed to delete {:?} edit {} from editgroup {}", entity_type, edit_id, editgroup_id))
+    }
+
+    /// Deletes an entity. If there is already an edit of the entity in the editgroup, that edit is
+    /// removed first.
     pub fn delete_entity(&mut self, specifier: Specifier, editgroup_id: String) -> Result<models::EntityEdit> {
+        let specifier = specifier.into_entity_specifier(self)?;
+        let existing_edit = self.find_existing_edit(&specifier, &editgroup_id)?;
+        self.delete_entity_replacing(specifier, editgroup_id, existing_edit)
+    }
+
+    /// Like `delete_entity()`, with the existing edit of the entity in the editgroup (if any)
+    /// already known
+    pub fn delete_entity_replacing(&mut self, specifier: Specifier, editgroup_id: String, existing_edit: Option<models::EntityEdit>) -> Result<models::EntityEdit> {
         use Specifier::*;
         let specifier = specifier.into_entity_specifier(self)?;
-        match specifier.clone() {
+        if let Some(ee) = &existing_edit {
+            check_replaceable_edit(&specifier, ee)?;
+            self.delete_entity_edit(specifier.entity_type().unwrap(), &editgroup_id, &ee.edit_id)?;
+        }
+        let result = match specifier.clone() {
             Release(fcid) => match self.rt.block_on(self.api.delete_release(editgroup_id, fcid))? {
                 fatcat_openapi::DeleteReleaseResponse::DeletedEntity(ee) => Ok(ee),
                 other => Err(anyhow!("{:?}", other)),
@@ -131,11 +331,13 @@ impl<'a> FatcatApiClient<'a> {
                 fatcat_openapi::DeleteWebcaptureResponse::DeletedEntity(ee) => Ok(ee),
                 other => Err(anyhow!("{:?}", other)),
             },
-            Editgroup(..) | Editor(..) => unimplemented!("deletion for this entity type"),
+            Editgroup(..) | Editor(..) => return Err(anyhow!("only entities can be deleted, not editgroups or editors: {:?}", specifier)),
+            ReleaseRevision(..) | WorkRevision(..) | ContainerRevision(..) | CreatorRevision(..) | FileRevision(..) | FileSetRevision(..) | WebCaptureRevision(..) => return Err(anyhow!("revisions are immutable; specify an entity ident instead")),
             Changelog(..) => return Err(anyhow!("mutating this entity type doesn't make sense")),
             EditorUsername(..) | ReleaseLookup(..) | ContainerLookup(..) | FileLookup(..) | CreatorLookup(..) =>
                 return Err(anyhow!("into_entity_specifier() didn't work?")),
-        }.with_context(|| format!("failed to delete {:?}", specifier))
+        }.with_context(|| format!("failed to delete {:?}", specifier));
+        replacement_result(result, &existing_edit, &editgroup_id)
     }
 
     pub fn create_entity_from_json(&mut self, entity_type: EntityType, json_str: &str, editgroup_id: String) -> Result<models::EntityEdit> {
@@ -185,6 +387,130 @@ impl<'a> FatcatApiClient<'a> {
         }.with_context(|| format!("parsing and creating {:?} entity", entity_type))
     }
 
+    /// Creates all the entities in a single new editgroup, which gets accepted automatically
+    pub fn create_batch_from_json(&mut self, entity_type: EntityType, json_str_list: &[String], description: Option<String>) -> Result<models::Editgroup> {
+        let eg = new_editgroup_model(description);
+        match entity_type {
+            EntityType::Release => {
+                let entity_list = json_str_list.iter()
+                    .map(|json_str| serde_json::from_str(json_str))
+                    .collect::<Result<Vec<models::ReleaseEntity>, serde_json::Error>>()?;
+                match self.rt.block_on(self.api.create_release_auto_batch(models::ReleaseAutoBatch::new(eg, entity_list)))? {
+                    fatcat_openapi::CreateReleaseAutoBatchResponse::CreatedEditgroup(eg) => Ok(eg),
+                    other => Err(anyhow!("{:?}", other)),
+                }
+            },
+            EntityType::Work => {
+                let entity_list = json_str_list.iter()
+                    .map(|json_str| serde_json::from_str(json_str))
+                    .collect::<Result<Vec<models::WorkEntity>, serde_json::Error>>()?;
+                match self.rt.block_on(self.api.create_work_auto_batch(models::WorkAutoBatch::new(eg, entity_list)))? {
+                    fatcat_openapi::CreateWorkAutoBatchResponse::CreatedEditgroup(eg) => Ok(eg),
+                    other => Err(anyhow!("{:?}", other)),
+                }
+            },
+            EntityType::Creator => {
+                let entity_list = json_str_list.iter()
+                    .map(|json_str| serde_json::from_str(json_str))
+                    .collect::<Result<Vec<models::CreatorEntity>, serde_json::Error>>()?;
+                match self.rt.block_on(self.api.create_creator_auto_batch(models::CreatorAutoBatch::new(eg, entity_list)))? {
+                    fatcat_openapi::CreateCreatorAutoBatchResponse::CreatedEditgroup(eg) => Ok(eg),
+                    other => Err(anyhow!("{:?}", other)),
+                }
+            },
+            EntityType::Container => {
+                let entity_list = json_str_list.iter()
+                    .map(|json_str| serde_json::from_str(json_str))
+                    .collect::<Result<Vec<models::ContainerEntity>, serde_json::Error>>()?;
+                match self.rt.block_on(self.api.create_container_auto_batch(models::ContainerAutoBatch::new(eg, entity_list)))? {
+                    fatcat_openapi::CreateContainerAutoBatchResponse::CreatedEditgroup(eg) => Ok(eg),
+                    other => Err(anyhow!("{:?}", other)),
+                }
+            },
+            EntityType::File => {
+                let entity_list = json_str_list.iter()
+                    .map(|json_str| serde_json::from_str(json_str))
+                    .collect::<Result<Vec<models::FileEntity>, serde_json::Error>>()?;
+                match self.rt.block_on(self.api.create_file_auto_batch(models::FileAutoBatch::new(eg, entity_list)))? {
+                    fatcat_openapi::CreateFileAutoBatchResponse::CreatedEditgroup(eg) => Ok(eg),
+                    other => Err(anyhow!("{:?}", other)),
+                }
+            },
+            EntityType::FileSet => {
+                let entity_list = json_str_list.iter()
+                    .map(|json_str| serde_json::from_str(json_str))
+                    .collect::<Result<Vec<models::FilesetEntity>, serde_json::Error>>()?;
+                match self.rt.block_on(self.api.create_fileset_auto_batch(models::FilesetAutoBatch::new(eg, entity_list)))? {
+                    fatcat_openapi::CreateFilesetAutoBatchResponse::CreatedEditgroup(eg) => Ok(eg),
+                    other => Err(anyhow!("{:?}", other)),
+                }
+            },
+            EntityType::WebCapture => {
+                let entity_list = json_str_list.iter()
+                    .map(|json_str| serde_json::from_str(json_str))
+                    .collect::<Result<Vec<models::WebcaptureEntity>, serde_json::Error>>()?;
+                match self.rt.block_on(self.api.create_webcapture_auto_batch(models::WebcaptureAutoBatch::new(eg, entity_list)))? {
+                    fatcat_openapi::CreateWebcaptureAutoBatchResponse::CreatedEditgroup(eg) => Ok(eg),
+                    other => Err(anyhow!("{:?}", other)),
+                }
+            },
+        }.with_context(|| format!("parsing and creating batch of {:?} entities", entity_type))
+    }
+
+    /// Like `update_entity_from_json()`, but first re-fetches the current version of the entity
+    /// (from any existing edit in the editgroup, otherwise the live version) and skips the update
+    /// if it wouldn't change anything. If `expected_revision` is provided (the revision the update
+    /// was based on) and the entity has since been updated, the update is also skipped, unless
+    /// `force` is set. Any existing edit of the entity in the editgroup is replaced, unless that
+    /// edit created the entity, which is an error.
+    pub fn update_entity_checked(&mut self, specifier: Specifier, json_str: &str, expected_revision: Option<String>, editgroup_id: String, force: bool) -> Result<UpdateResult> {
+        let specifier = specifier.into_entity_specifier(self)?;
+        let existing_edit = self.find_existing_edit(&specifier, &editgroup_id)?;
+        if let Some(skipped) = self.check_update(&specifier, json_str, expected_revision, &existing_edit, force)? {
+            return Ok(skipped);
+        }
+        Ok(UpdateResult::Updated(self.update_entity_replacing(specifier, json_str, editgroup_id, existing_edit)?))
+    }
+
+    /// The checks of `update_entity_checked()`, without making any edits: returns the result if
+    /// the update should be skipped (`Unchanged` or `Conflict`), or `None` if it should be made.
+    /// `specifier` must already be an entity specifier.
+    pub fn check_update(&mut self, specifier: &Specifier, json_str: &str, expected_revision: Option<String>, existing_edit: &Option<models::EntityEdit>, force: bool) -> Result<Option<UpdateResult>> {
+        let current_json = self.get_entity_with_edit(specifier, existing_edit)?.to_json_string()?;
+        let current: serde_json::Value = serde_json::from_str(&current_json)?;
+        if let (Some(expected_revision), Some(current_revision)) = (expected_revision, current["revision"].as_str()) {
+            // input based on the live version is fine, even if there is an edit in the editgroup
+            let base_revision = existing_edit.as_ref().and_then(|ee| ee.prev_revision.as_ref());
+            if expected_revision != current_revision && Some(&expected_revision) != base_revision && !force {
+                return Ok(Some(UpdateResult::Conflict {
+                    specifier: specifier.clone(),
+                    expected_revision,
+                    current_revision: current_revision.to_string(),
+                }));
+            }
+        }
+        let updated: serde_json::Value = serde_json::from_str(json_str).context("parsing entity JSON")?;
+        if entity_content(&current) == entity_content(&updated) {
+            return Ok(Some(UpdateResult::Unchanged(specifier.clone())));
+        }
+        Ok(None)
+    }
+
+    /// Updates an entity (like `update_entity_from_json()`), first removing the existing edit of
+    /// the entity in the editgroup, if any.
+    pub fn update_entity_replacing(&mut self, specifier: Specifier, json_str: &str, editgroup_id: String, existing_edit: Option<models::EntityEdit>) -> Result<models::EntityEdit> {
+        let specifier = specifier.into_entity_specifier(self)?;
+        // make sure the replacement is valid before removing anything from the editgroup
+        let entity_type = specifier.entity_type().unwrap();
+        check_entity_json(entity_type, json_str)?;
+        if let Some(ee) = &existing_edit {
+            check_replaceable_edit(&specifier, ee)?;
+            self.delete_entity_edit(entity_type, &editgroup_id, &ee.edit_id)?;
+        }
+        let result = self.update_entity_from_json(specifier, json_str, editgroup_id.clone());
+        replacement_result(result, &existing_edit, &editgroup_id)
+    }
+
     pub fn update_entity_from_json(&mut self, specifier: Specifier, json_str: &str, editgroup_id: String) -> Result<models::EntityEdit> {
         use Specifier::*;
         let specifier = specifier.into_entity_specifier(self)?;
@@ -217,10 +543,220 @@ impl<'a> FatcatApiClient<'a> {
                 fatcat_openapi::UpdateWebcaptureResponse::UpdatedEntity(ee) => Ok(ee),
                 other => Err(anyhow!("{:?}", other)),
             },
-            Editgroup(..) | Editor(..) => unimplemented!("updates for this entity type"),
+            Editgroup(..) | Editor(..) => return Err(anyhow!("only entities can be updated this way, not editgroups or editors: {:?}", specifier)),
+            ReleaseRevision(..) | WorkRevision(..) | ContainerRevision(..) | CreatorRevision(..) | FileRevision(..) | FileSetRevision(..) | WebCaptureRevision(..) => return Err(anyhow!("revisions are immutable; specify an entity ident instead")),
             Changelog(..) => return Err(anyhow!("deleting this entity type doesn't make sense")),
             EditorUsername(..) | ReleaseLookup(..) | ContainerLookup(..) | FileLookup(..) | CreatorLookup(..) =>
                 return Err(anyhow!("into_entity_specifier() didn't work?")),
         }.with_context(|| format!("failed to update {:?}", specifier))
     }
 }
+
+/// Keeps track of the "current" editgroup when making many edits, starting a new editgroup every
+/// `batch_size` edits. If no initial editgroup is provided, one is created on first use.
+///
+/// The edits in the current editgroup are kept track of as well, so existing edits can be found
+/// without re-fetching the editgroup for every edit.
+pub struct EditgroupBatcher {
+    editgroup_id: Option<String>,
+    batch_size: usize,
+    description: Option<String>,
+    /// Number of edits in the current editgroup, including any made before this batcher
+    edit_count: usize,
+    /// Edits in the current editgroup; fetched on first use for an existing editgroup
+    edits: Option<models::EditgroupEdits>,
+}
+
+impl EditgroupBatcher {
+
+    pub fn new(editgroup_id: Option<String>, batch_size: usize, description: Option<String>) -> Self {
+        EditgroupBatcher {
+            editgroup_id,
+            batch_size,
+            description,
+            edit_count: 0,
+            edits: None,
+        }
+    }
+
+    /// Fetches the edits of an existing editgroup the first time they are needed, so edits made
+    /// before this batcher count towards `batch_size`
+    fn load_edits(&mut self, api_client: &mut FatcatApiClient) -> Result<()> {
+        let eg_id = match (&self.editgroup_id, &self.edits) {
+            (Some(eg_id), None) => eg_id.clone(),
+            _ => return Ok(()),
+        };
+        let eg = api_client.get_editgroup(eg_id)?;
+        let edits = eg.edits.unwrap_or_else(models::EditgroupEdits::new);
+        self.edit_count = EntityType::ALL.iter()
+            .map(|entity_type| editgroup_edits_of_type(&edits, *entity_type).len())
+            .sum();
+        self.edits = Some(edits);
+        Ok(())
+    }
+
+    /// Editgroup the next edit would go in to, if it already exists (and has room)
+    fn current_editgroup_id(&self) -> Option<&String> {
+        match &self.editgroup_id {
+            Some(eg_id) if self.edit_count < self.batch_size => Some(eg_id),
+            _ => None,
+        }
+    }
+
+    /// Returns the editgroup the next edit should go in to, creating a new one if needed
+    pub fn editgroup_id(&mut self, api_client: &mut FatcatApiClient) -> Result<String> {
+        self.load_edits(api_client)?;
+        if let Some(eg_id) = self.current_editgroup_id() {
+            return Ok(eg_id.to_string());
+        }
+        let eg = api_client.create_editgroup(self.description.clone())?;
+        let eg_id = eg.editgroup_id.context("expected editgroup_id in API response")?;
+        eprintln!("Created new editgroup: editgroup_{}", eg_id);
+        self.editgroup_id = Some(eg_id.clone());
+        self.edit_count = 0;
+        self.edits = Some(models::EditgroupEdits::new());
+        Ok(eg_id)
+    }
+
+    /// Finds an existing edit of the entity in the editgroup the next edit would go in to. Doesn't
+    /// create an editgroup; if the next edit needs a new editgroup, there is no existing edit.
+    pub fn existing_edit(&mut self, api_client: &mut FatcatApiClient, specifier: &Specifier) -> Result<Option<models::EntityEdit>> {
+        self.load_edits(api_client)?;
+        if self.current_editgroup_id().is_none() {
+            return Ok(None);
+        }
+        Ok(self.edits.as_ref().and_then(|edits| find_edit(edits, specifier)))
+    }
+
+    /// Records a new edit in the current editgroup, replacing any earlier edit of the same entity
+    /// (which doesn't count towards `batch_size` again).
+    pub fn record_edit(&mut self, entity_type: EntityType, ee: &models::EntityEdit) {
+        let edits = match self.edits.as_mut() {
+            Some(edits) => edits,
+            // not fetched yet; the count is set (including this edit) when it is
+            None => return,
+        };
+        let edit_list = editgroup_edits_of_type_mut(edits, entity_type).get_or_insert_with(Vec::new);
+        let before = edit_list.len();
+        edit_list.retain(|existing| existing.ident != ee.ident);
+        if edit_list.len() == before {
+            self.edit_count += 1;
+        }
+        edit_list.push(ee.clone());
+    }
+
+    /// Like `FatcatApiClient::update_entity_checked()`, in the current editgroup. A new editgroup
+    /// is only created once there is an actual change to make.
+    pub fn update_entity_checked(&mut self, api_client: &mut FatcatApiClient, specifier: Specifier, json_str: &str, expected_revision: Option<String>, force: bool) -> Result<UpdateResult> {
+        let specifier = specifier.into_entity_specifier(api_client)?;
+        let entity_type = specifier.entity_type().context("expected an entity specifier")?;
+        let existing_edit = self.existing_edit(api_client, &specifier)?;
+        if let Some(skipped) = api_client.check_update(&specifier, json_str, expected_revision, &existing_edit, force)? {
+            return Ok(skipped);
+        }
+        let editgroup_id = self.editgroup_id(api_client)?;
+        let ee = api_client.update_entity_replacing(specifier, json_str, editgroup_id, existing_edit)?;
+        self.record_edit(entity_type, &ee);
+        Ok(UpdateResult::Updated(ee))
+    }
+
+    /// Like `FatcatApiClient::delete_entity()`, in the current editgroup
+    pub fn delete_entity(&mut self, api_client: &mut FatcatApiClient, specifier: Specifier) -> Result<models::EntityEdit> {
+        let specifier = specifier.into_entity_specifier(api_client)?;
+        let entity_type = specifier.entity_type().context("expected an entity specifier")?;
+        let existing_edit = self.existing_edit(api_client, &specifier)?;
+        let editgroup_id = self.editgroup_id(api_client)?;
+        let ee = api_client.delete_entity_replacing(specifier, editgroup_id, existing_edit)?;
+        self.record_edit(entity_type, &ee);
+        Ok(ee)
+    }
+}
+
+/// All the edits of a single entity type in an editgroup
+pub fn editgroup_edits_of_type(edits: &models::EditgroupEdits, entity_type: EntityType) -> Vec<models::EntityEdit> {
+    match entity_type {
+        EntityType::Release => &edits.releases,
+        EntityType::Work => &edits.works,
+        EntityType::Container => &edits.containers,
+        EntityType::Creator => &edits.creators,
+        EntityType::File => &edits.files,
+        EntityType::FileSet => &edits.filesets,
+        EntityType::WebCapture => &edits.webcaptures,
+    }.clone().unwrap_or_default()
+}
+
+/// Parses entity JSON into the API model for the entity type, without doing anything with it
+fn check_entity_json(entity_type: EntityType, json_str: &str) -> Result<()> {
+    match entity_type {
+        EntityType::Release => serde_json::from_str::<models::ReleaseEntity>(json_str).map(|_| ()),
+        EntityType::Work => serde_json::from_str::<models::WorkEntity>(json_str).map(|_| ()),
+        EntityType::Container => serde_json::from_str::<models::ContainerEntity>(json_str).map(|_| ()),
+        EntityType::Creator => serde_json::from_str::<models::CreatorEntity>(json_str).map(|_| ()),
+        EntityType::File => serde_json::from_str::<models::FileEntity>(json_str).map(|_| ()),
+        EntityType::FileSet => serde_json::from_str::<models::FilesetEntity>(json_str).map(|_| ()),
+        EntityType::WebCapture => serde_json::from_str::<models::WebcaptureEntity>(json_str).map(|_| ()),
+    }.with_context(|| format!("parsing {:?} entity JSON", entity_type))
+}
+
+/// Existing edits can only be replaced if they modify an entity which already existed; removing an
+/// edit which created the entity would remove the entity itself.
+fn check_replaceable_edit(specifier: &Specifier, existing_edit: &models::EntityEdit) -> Result<()> {
+    if existing_edit.prev_revision.is_none() {
+        return Err(anyhow!("{:?} was created in this editgroup (edit {}); update or remove that edit directly instead", specifier, existing_edit.edit_id));
+    }
+    Ok(())
+}
+
+/// If an existing edit was removed to make room for a new edit, and the new edit then failed, the
+/// old edit is gone from the editgroup; say so, instead of only reporting the API error.
+fn replacement_result<T>(result: Result<T>, existing_edit: &Option<models::EntityEdit>, editgroup_id: &str) -> Result<T> {
+    match (result, existing_edit) {
+        (Err(err), Some(ee)) => Err(err.context(format!(
+            "previous edit {} (revision {}) was already removed from editgroup {}, and has not been restored",
+            ee.edit_id, ee.revision.as_deref().unwrap_or("-"), editgroup_id))),
+        (result, _) => result,
+    }
+}
+
+fn editgroup_edits_of_type_mut(edits: &mut models::EditgroupEdits, entity_type: EntityType) -> &mut Option<Vec<models::EntityEdit>> {
+    match entity_type {
+        EntityType::Release => &mut edits.releases,
+        EntityType::Work => &mut edits.works,
+        EntityType::Container => &mut edits.containers,
+        EntityType::Creator => &mut edits.creators,
+        EntityType::File => &mut edits.files,
+        EntityType::FileSet => &mut edits.filesets,
+        EntityType::WebCapture => &mut edits.webcaptures,
+    }
+}
+
+/// The edit of the given entity in a set of editgroup edits, if any. Only ident specifiers (not
+/// lookups or revisions) can match an edit.
+fn find_edit(edits: &models::EditgroupEdits, specifier: &Specifier) -> Option<models::EntityEdit> {
+    use Specifier::*;
+    let ident = match specifier {
+        Release(fcid) | Work(fcid) | Container(fcid) | Creator(fcid) | File(fcid) | FileSet(fcid) | WebCapture(fcid) => fcid,
+        _ => return None,
+    };
+    editgroup_edits_of_type(edits, specifier.entity_type()?).into_iter().find(|ee| &ee.ident == ident)
+}
+
+/// Specifier for the revision from an existing edit (if it has one), otherwise the entity itself
+fn edit_base_specifier(specifier: &Specifier, existing_edit: &Option<models::EntityEdit>) -> Specifier {
+    match (specifier.entity_type(), existing_edit.as_ref().and_then(|ee| ee.revision.as_ref())) {
+        (Some(entity_type), Some(rev_id)) => Specifier::from_revision(entity_type, rev_id),
+        _ => specifier.clone(),
+    }
+}
+
+/// Editgroup model (not yet created) with fatcat-cli agent metadata
+fn new_editgroup_model(description: Option<String>) -> models::Editgroup {
+    let mut eg = models::Editgroup::new();
+    eg.description = description;
+    eg.extra = Some({
+        let mut extra = std::collections::HashMap::new();
+        extra.insert("agent".to_string(), serde_json::Value::String("fatcat-cli".to_string()));
+        extra
+    });
+    eg
+}
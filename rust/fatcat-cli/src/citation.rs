@@ -0,0 +1,358 @@
+
+use chrono::Datelike;
+use serde_json::{json, Value};
+use fatcat_openapi::models;
+
+
+/// Renders release entities as citations, for use in papers and reference managers.
+///
+/// Best results are with the container expanded (`--expand container`); contributor names come
+/// from the contribs list, so creators don't need to be expanded.
+pub trait ApiModelCite {
+    fn to_bibtex(&self) -> String;
+    fn to_csl_json(&self) -> Value;
+    fn to_ris(&self) -> String;
+}
+
+// release_type values which are also valid CSL item types
+const CSL_TYPES: &[&str] = &[
+    "article", "article-journal", "article-magazine", "article-newspaper", "book", "chapter",
+    "dataset", "entry", "entry-encyclopedia", "letter", "manuscript", "paper-conference", "post",
+    "post-weblog", "report", "review", "software", "speech", "standard", "thesis", "webpage",
+];
+
+fn bibtex_type(release_type: Option<&str>) -> &'static str {
+    match release_type {
+        Some("article-journal") | Some("article") => "article",
+        Some("paper-conference") => "inproceedings",
+        Some("book") => "book",
+        Some("chapter") | Some("entry") | Some("entry-encyclopedia") => "incollection",
+        Some("thesis") => "phdthesis",
+        Some("report") => "techreport",
+        _ => "misc",
+    }
+}
+
+fn ris_type(release_type: Option<&str>) -> &'static str {
+    match release_type {
+        Some("article-journal") | Some("article") => "JOUR",
+        Some("article-magazine") => "MGZN",
+        Some("article-newspaper") => "NEWS",
+        Some("paper-conference") => "CPAPER",
+        Some("book") => "BOOK",
+        Some("chapter") => "CHAP",
+        Some("entry") | Some("entry-encyclopedia") => "ENCYC",
+        Some("thesis") => "THES",
+        Some("report") => "RPRT",
+        Some("dataset") => "DATA",
+        Some("software") => "COMP",
+        _ => "GEN",
+    }
+}
+
+/// (surname, given name) if known, otherwise the raw name as a single part
+fn contrib_name(contrib: &models::ReleaseContrib) -> Option<(String, Option<String>)> {
+    let creator = contrib.creator.as_ref();
+    let surname = contrib.surname.clone().or_else(|| creator.and_then(|c| c.surname.clone()));
+    let given_name = contrib.given_name.clone().or_else(|| creator.and_then(|c| c.given_name.clone()));
+    match surname {
+        Some(surname) => Some((surname, given_name)),
+        None => contrib.raw_name.clone()
+            .or_else(|| creator.and_then(|c| c.display_name.clone()))
+            .map(|raw_name| (raw_name, None)),
+    }
+}
+
+/// Contributors with the given role, in index order. Contribs with no role are counted as authors.
+fn contribs_with_role<'a>(release: &'a models::ReleaseEntity, role: &str) -> Vec<&'a models::ReleaseContrib> {
+    let mut contribs: Vec<&models::ReleaseContrib> = match release.contribs {
+        Some(ref contribs) => contribs.iter()
+            .filter(|c| c.role.as_deref().unwrap_or("author") == role)
+            .collect(),
+        None => vec![],
+    };
+    contribs.sort_by_key(|c| c.index.unwrap_or(std::i64::MAX));
+    contribs
+}
+
+fn release_url(release: &models::ReleaseEntity) -> Option<String> {
+    release.ident.as_ref().map(|ident| format!("https://fatcat.wiki/release/{}", ident))
+}
+
+fn container_name(release: &models::ReleaseEntity) -> Option<String> {
+    release.container.as_ref().and_then(|c| c.name.clone())
+}
+
+fn release_year(release: &models::ReleaseEntity) -> Option<i64> {
+    release.release_year.or_else(|| release.release_date.map(|d| d.year() as i64))
+}
+
+/// Escapes LaTeX special characters in a BibTeX field value
+fn bibtex_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            '{' | '}' | '&' | '%' | '$' | '#' | '_' => {
+                escaped.push('\\');
+                escaped.push(c);
+            },
+            '\n' | '\t' => escaped.push(' '),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// RIS is line-oriented, so values can't contain newlines
+fn ris_clean(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+fn bibtex_names(contribs: &[&models::ReleaseContrib]) -> Option<String> {
+    let names: Vec<String> = contribs.iter().filter_map(|c| contrib_name(c)).map(|(surname, given_name)| match given_name {
+        Some(given_name) => format!("{}, {}", surname, given_name),
+        // braces keep BibTeX from splitting a full raw name into parts
+        None => format!("{{{}}}", surname),
+    }).collect();
+    if names.is_empty() {
+        None
+    } else {
+        Some(names.join(" and "))
+    }
+}
+
+fn csl_names(contribs: &[&models::ReleaseContrib]) -> Option<Value> {
+    let names: Vec<Value> = contribs.iter().filter_map(|c| contrib_name(c)).map(|(surname, given_name)| match given_name {
+        Some(given_name) => json!({"family": surname, "given": given_name}),
+        None => json!({"literal": surname}),
+    }).collect();
+    if names.is_empty() {
+        None
+    } else {
+        Some(Value::Array(names))
+    }
+}
+
+impl ApiModelCite for models::ReleaseEntity {
+
+    fn to_bibtex(&self) -> String {
+        let key = match self.ident {
+            Some(ref ident) => format!("fatcat:{}", ident),
+            None => "fatcat".to_string(),
+        };
+        let mut fields: Vec<(&str, String)> = vec![];
+        if let Some(ref title) = self.title {
+            fields.push(("title", bibtex_escape(title)));
+        }
+        if let Some(authors) = bibtex_names(&contribs_with_role(self, "author")) {
+            fields.push(("author", authors));
+        }
+        if let Some(editors) = bibtex_names(&contribs_with_role(self, "editor")) {
+            fields.push(("editor", editors));
+        }
+        if let Some(container_name) = container_name(self) {
+            let field = match bibtex_type(self.release_type.as_deref()) {
+                "inproceedings" | "incollection" => "booktitle",
+                _ => "journal",
+            };
+            fields.push((field, bibtex_escape(&container_name)));
+        }
+        if let Some(year) = release_year(self) {
+            fields.push(("year", year.to_string()));
+        }
+        for (field, val) in [("volume", &self.volume), ("number", &self.issue), ("publisher", &self.publisher)].iter() {
+            if let Some(val) = val {
+                fields.push((*field, bibtex_escape(val)));
+            }
+        }
+        if let Some(ref pages) = self.pages {
+            fields.push(("pages", bibtex_escape(&pages.replace('-', "--"))));
+        }
+        if let Some(ref doi) = self.ext_ids.doi {
+            fields.push(("doi", doi.clone()));
+        }
+        if let Some(ref isbn13) = self.ext_ids.isbn13 {
+            fields.push(("isbn", isbn13.clone()));
+        }
+        if let Some(ref arxiv) = self.ext_ids.arxiv {
+            fields.push(("eprint", arxiv.clone()));
+            fields.push(("archivePrefix", "arXiv".to_string()));
+        }
+        if let Some(url) = release_url(self) {
+            fields.push(("url", url));
+        }
+
+        let mut bibtex = format!("@{}{{{}", bibtex_type(self.release_type.as_deref()), key);
+        for (field, val) in fields {
+            bibtex.push_str(&format!(",\n  {} = {{{}}}", field, val));
+        }
+        bibtex.push_str("\n}\n");
+        bibtex
+    }
+
+    fn to_csl_json(&self) -> Value {
+        let mut csl = serde_json::Map::new();
+        csl.insert("id".to_string(), json!(self.ident.as_ref().map(|ident| format!("release_{}", ident))));
+        let csl_type = match self.release_type.as_deref() {
+            Some(t) if CSL_TYPES.contains(&t) => t,
+            _ => "article",
+        };
+        csl.insert("type".to_string(), json!(csl_type));
+        csl.insert("title".to_string(), json!(self.title));
+        csl.insert("author".to_string(), json!(csl_names(&contribs_with_role(self, "author"))));
+        csl.insert("editor".to_string(), json!(csl_names(&contribs_with_role(self, "editor"))));
+        csl.insert("container-title".to_string(), json!(container_name(self)));
+        let issued = match (self.release_date, release_year(self)) {
+            (Some(date), _) => Some(json!({"date-parts": [[date.year(), date.month(), date.day()]]})),
+            (None, Some(year)) => Some(json!({"date-parts": [[year]]})),
+            (None, None) => None,
+        };
+        csl.insert("issued".to_string(), json!(issued));
+        csl.insert("volume".to_string(), json!(self.volume));
+        csl.insert("issue".to_string(), json!(self.issue));
+        csl.insert("page".to_string(), json!(self.pages));
+        csl.insert("publisher".to_string(), json!(self.publisher));
+        csl.insert("language".to_string(), json!(self.language));
+        csl.insert("DOI".to_string(), json!(self.ext_ids.doi));
+        csl.insert("PMID".to_string(), json!(self.ext_ids.pmid));
+        csl.insert("PMCID".to_string(), json!(self.ext_ids.pmcid));
+        csl.insert("ISBN".to_string(), json!(self.ext_ids.isbn13));
+        csl.insert("ISSN".to_string(), json!(self.container.as_ref().and_then(|c| c.issnl.clone())));
+        csl.insert("URL".to_string(), json!(release_url(self)));
+        // CSL processors don't like null fields
+        Value::Object(csl.into_iter().filter(|(_, v)| !v.is_null()).collect())
+    }
+
+    fn to_ris(&self) -> String {
+        let mut lines: Vec<(&str, String)> = vec![("TY", ris_type(self.release_type.as_deref()).to_string())];
+        if let Some(ref title) = self.title {
+            lines.push(("TI", ris_clean(title)));
+        }
+        for (tag, role) in [("AU", "author"), ("ED", "editor")].iter() {
+            for contrib in contribs_with_role(self, role) {
+                match contrib_name(contrib) {
+                    Some((surname, Some(given_name))) => lines.push((*tag, ris_clean(&format!("{}, {}", surname, given_name)))),
+                    Some((raw_name, None)) => lines.push((*tag, ris_clean(&raw_name))),
+                    None => (),
+                }
+            }
+        }
+        if let Some(year) = release_year(self) {
+            lines.push(("PY", year.to_string()));
+        }
+        if let Some(date) = self.release_date {
+            lines.push(("DA", date.format("%Y/%m/%d").to_string()));
+        }
+        if let Some(container_name) = container_name(self) {
+            lines.push(("T2", ris_clean(&container_name)));
+        }
+        if let Some(ref volume) = self.volume {
+            lines.push(("VL", ris_clean(volume)));
+        }
+        if let Some(ref issue) = self.issue {
+            lines.push(("IS", ris_clean(issue)));
+        }
+        if let Some(ref pages) = self.pages {
+            let mut parts = pages.splitn(2, '-');
+            if let Some(start) = parts.next() {
+                lines.push(("SP", ris_clean(start)));
+            }
+            if let Some(end) = parts.next() {
+                lines.push(("EP", ris_clean(end)));
+            }
+        }
+        if let Some(ref publisher) = self.publisher {
+            lines.push(("PB", ris_clean(publisher)));
+        }
+        if let Some(ref doi) = self.ext_ids.doi {
+            lines.push(("DO", doi.clone()));
+        }
+        if let Some(issn) = self.ext_ids.isbn13.clone().or_else(|| self.container.as_ref().and_then(|c| c.issnl.clone())) {
+            lines.push(("SN", issn));
+        }
+        if let Some(ref language) = self.language {
+            lines.push(("LA", language.clone()));
+        }
+        if let Some(url) = release_url(self) {
+            lines.push(("UR", url));
+        }
+        lines.push(("ER", "".to_string()));
+
+        let mut ris = String::new();
+        for (tag, val) in lines {
+            ris.push_str(&format!("{}  - {}\n", tag, val));
+        }
+        ris
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example_release() -> models::ReleaseEntity {
+        serde_json::from_value(json!({
+            "ident": "aaaaaaaaaaaaarceaaaaaaaaai",
+            "title": "Some Results & Discussion",
+            "release_type": "article-journal",
+            "release_date": "2019-05-03",
+            "release_year": 2019,
+            "volume": "12",
+            "issue": "3",
+            "pages": "100-110",
+            "ext_ids": {"doi": "10.123/abc"},
+            "container": {"name": "Journal of Examples", "issnl": "1234-5678"},
+            "contribs": [
+                {"index": 1, "raw_name": "Jane Doe", "role": "author"},
+                {"index": 0, "raw_name": "A. Person", "given_name": "Alice", "surname": "Person", "role": "author"},
+                {"raw_name": "Ed Itor", "role": "editor"},
+            ],
+        })).unwrap()
+    }
+
+    #[test]
+    fn test_to_bibtex() -> () {
+        let bibtex = example_release().to_bibtex();
+        assert!(bibtex.starts_with("@article{fatcat:aaaaaaaaaaaaarceaaaaaaaaai,\n"));
+        assert!(bibtex.contains("  title = {Some Results \\& Discussion},\n"));
+        assert!(bibtex.contains("  author = {Person, Alice and {Jane Doe}},\n"));
+        assert!(bibtex.contains("  editor = {{Ed Itor}},\n"));
+        assert!(bibtex.contains("  journal = {Journal of Examples},\n"));
+        assert!(bibtex.contains("  pages = {100--110},\n"));
+        assert!(bibtex.ends_with("  url = {https://fatcat.wiki/release/aaaaaaaaaaaaarceaaaaaaaaai}\n}\n"));
+
+        let minimal: models::ReleaseEntity = serde_json::from_value(json!({"ext_ids": {}})).unwrap();
+        assert_eq!(minimal.to_bibtex(), "@misc{fatcat\n}\n");
+    }
+
+    #[test]
+    fn test_to_csl_json() -> () {
+        let csl = example_release().to_csl_json();
+        assert_eq!(csl["type"], "article-journal");
+        assert_eq!(csl["author"], json!([{"family": "Person", "given": "Alice"}, {"literal": "Jane Doe"}]));
+        assert_eq!(csl["issued"], json!({"date-parts": [[2019, 5, 3]]}));
+        assert_eq!(csl["container-title"], "Journal of Examples");
+        assert_eq!(csl["DOI"], "10.123/abc");
+        assert!(csl.get("PMID").is_none());
+
+        let mut release = example_release();
+        release.release_type = Some("stub".to_string());
+        assert_eq!(release.to_csl_json()["type"], "article");
+    }
+
+    #[test]
+    fn test_to_ris() -> () {
+        let ris = example_release().to_ris();
+        let lines: Vec<&str> = ris.lines().collect();
+        assert_eq!(lines[0], "TY  - JOUR");
+        assert_eq!(lines[2], "AU  - Person, Alice");
+        assert_eq!(lines[3], "AU  - Jane Doe");
+        assert_eq!(lines[4], "ED  - Ed Itor");
+        assert!(lines.contains(&"DA  - 2019/05/03"));
+        assert!(lines.contains(&"SP  - 100"));
+        assert!(lines.contains(&"EP  - 110"));
+        assert!(lines.contains(&"SN  - 1234-5678"));
+        assert_eq!(lines.last(), Some(&"ER  - "));
+    }
+}
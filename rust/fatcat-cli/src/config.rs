@@ -0,0 +1,260 @@
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use anyhow::{Result, anyhow, Context};
+use std::str::FromStr;
+use log::warn;
+use crate::{config_dir, OutputFormat};
+
+
+/// Example config file, used as a starting point by `config edit`
+pub const CONFIG_TEMPLATE: &str = r#"# fatcat-cli configuration
+#
+# Select a profile with --profile (or FATCAT_PROFILE); command-line flags and environment
+# variables override profile settings, except that an API host and token are always used
+# together: a token from flags or environment is never sent to a profile's api_host.
+
+#default_profile = "prod"
+
+#[profiles.prod]
+#api_host = "https://api.fatcat.wiki"
+#search_host = "https://search.fatcat.wiki"
+#api_token_command = "pass show fatcat/prod"
+#editgroup_description = "manual edits"
+#output = "table"
+
+#[profiles.qa]
+#api_host = "https://api.qa.fatcat.wiki"
+#search_host = "https://search.qa.fatcat.wiki"
+
+#[profiles.dev]
+#api_host = "http://localhost:9411"
+#api_token = "..."
+"#;
+
+#[derive(Debug, Default, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct CliConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_profile: Option<String>,
+
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+/// Named set of connection settings and preferences. All fields are optional.
+#[derive(Debug, Default, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_host: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search_host: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_token: Option<String>,
+
+    /// Shell command which prints the API token to stdout (eg, from a password manager)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_token_command: Option<String>,
+
+    /// Description for newly created editgroups, when none is given on the command line
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub editgroup_description: Option<String>,
+
+    /// Default for --output, like "json" or "table"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+}
+
+impl CliConfig {
+
+    pub fn path() -> Result<PathBuf> {
+        Ok(config_dir()?.join("config.toml"))
+    }
+
+    /// Reads the config file; a missing file is the same as an empty config.
+    pub fn load() -> Result<Self> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let raw = std::fs::read_to_string(&path)
+            .with_context(|| format!("reading config file {:?}", path))?;
+        Self::from_toml(&raw).with_context(|| format!("parsing config file {:?}", path))
+    }
+
+    pub fn from_toml(raw: &str) -> Result<Self> {
+        let config: Self = toml::from_str(raw)?;
+        if let Some(ref name) = config.default_profile {
+            if !config.profiles.contains_key(name) {
+                return Err(anyhow!("default_profile '{}' is not defined", name));
+            }
+        }
+        for (name, profile) in config.profiles.iter() {
+            profile.output_format().with_context(|| format!("profile '{}'", name))?;
+        }
+        Ok(config)
+    }
+
+    /// Returns the named profile, or the default profile (if any) when no name is given.
+    pub fn profile(&self, name: Option<&str>) -> Result<Profile> {
+        match name.or_else(|| self.default_profile.as_ref().map(|s| s.as_str())) {
+            Some(name) => match self.profiles.get(name) {
+                Some(profile) => Ok(profile.clone()),
+                None => Err(anyhow!("no profile named '{}' in config file", name)),
+            },
+            None => Ok(Profile::default()),
+        }
+    }
+
+    /// Copy of the config with any API tokens blanked out, for display
+    pub fn redacted(&self) -> Self {
+        let mut config = self.clone();
+        for profile in config.profiles.values_mut() {
+            if profile.api_token.is_some() {
+                profile.api_token = Some("<redacted>".to_string());
+            }
+        }
+        config
+    }
+}
+
+impl Profile {
+
+    pub fn output_format(&self) -> Result<Option<OutputFormat>> {
+        match self.output {
+            Some(ref output) => Ok(Some(OutputFormat::from_str(output)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the configured API token, running `api_token_command` if needed.
+    pub fn api_token(&self) -> Result<Option<String>> {
+        if let Some(ref token) = self.api_token {
+            return Ok(Some(token.clone()));
+        }
+        let command = match self.api_token_command {
+            Some(ref command) => command,
+            None => return Ok(None),
+        };
+        let output = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stderr(std::process::Stdio::inherit())
+            .output()
+            .with_context(|| format!("running api_token_command: {}", command))?;
+        if !output.status.success() {
+            return Err(anyhow!("api_token_command exited with non-success status code ({})", output.status.code().map(|v| v.to_string()).unwrap_or_else(|| "N/A".to_string())));
+        }
+        let token = String::from_utf8(output.stdout).context("api_token_command output")?.trim().to_string();
+        if token.is_empty() {
+            return Err(anyhow!("api_token_command printed an empty token"));
+        }
+        Ok(Some(token))
+    }
+
+    /// Returns the API host and token to use, given those from flags or environment variables.
+    ///
+    /// The host and token always come from the same place: a token from flags or the environment
+    /// is likely for some other host than the profile's, and must not be sent to it. If the profile
+    /// sets an API host, the profile's host and token are used, unless a different host is given
+    /// by flags or environment, in which case that host and token are used instead. For an
+    /// explicitly selected profile (`selected`), a conflicting API host is an error.
+    pub fn api_host_and_token(&self, selected: bool, api_host: Option<String>, api_token: Option<String>) -> Result<(Option<String>, Option<String>)> {
+        match (&self.api_host, api_host) {
+            (Some(profile_host), Some(host)) if &host != profile_host => {
+                if selected {
+                    return Err(anyhow!("API host {} conflicts with the selected profile's api_host ({})", host, profile_host));
+                }
+                Ok((Some(host), api_token))
+            },
+            (Some(profile_host), _) => {
+                if api_token.is_some() {
+                    warn!("ignoring API token from flags or environment; using the profile's token for {}", profile_host);
+                }
+                Ok((Some(profile_host.clone()), self.api_token()?))
+            },
+            (None, api_host) => {
+                let api_token = match api_token {
+                    Some(token) => Some(token),
+                    None => self.api_token()?,
+                };
+                Ok((api_host, api_token))
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_profiles() -> () {
+        let raw = r#"
+default_profile = "prod"
+
+[profiles.prod]
+api_host = "https://api.fatcat.wiki"
+output = "json"
+
+[profiles.dev]
+api_host = "http://localhost:9411"
+api_token = "AgEPZGV2LmZhdGNhdC53aWtp"
+editgroup_description = "testing"
+"#;
+        let config = CliConfig::from_toml(raw).unwrap();
+        assert_eq!(config.profile(None).unwrap().api_host, Some("https://api.fatcat.wiki".to_string()));
+        assert_eq!(config.profile(None).unwrap().output_format().unwrap(), Some(OutputFormat::Json));
+        let dev = config.profile(Some("dev")).unwrap();
+        assert_eq!(dev.api_host, Some("http://localhost:9411".to_string()));
+        assert_eq!(dev.api_token().unwrap(), Some("AgEPZGV2LmZhdGNhdC53aWtp".to_string()));
+        assert!(config.profile(Some("qa")).is_err());
+        assert_eq!(config.redacted().profiles["dev"].api_token, Some("<redacted>".to_string()));
+
+        let roundtrip = CliConfig::from_toml(&toml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(roundtrip, config);
+
+        // empty config (or only comments) is fine, and has no profiles
+        assert_eq!(CliConfig::from_toml(CONFIG_TEMPLATE).unwrap(), CliConfig::default());
+        assert_eq!(CliConfig::default().profile(None).unwrap(), Profile::default());
+
+        assert!(CliConfig::from_toml("default_profile = \"qa\"").is_err());
+        assert!(CliConfig::from_toml("[profiles.qa]\napi_hots = \"https://api.qa.fatcat.wiki\"").is_err());
+        assert!(CliConfig::from_toml("[profiles.qa]\noutput = \"xml\"").is_err());
+    }
+
+    #[test]
+    fn test_api_host_and_token() -> () {
+        let profile = Profile {
+            api_host: Some("http://localhost:9411".to_string()),
+            api_token: Some("AgEPZGV2".to_string()),
+            ..Default::default()
+        };
+        let env_host = Some("https://api.fatcat.wiki".to_string());
+        let env_token = Some("AgEPcHJvZA".to_string());
+        // default profile: a different host from flags or environment takes priority, along with
+        // its token; otherwise the profile's token is never mixed with another host
+        assert_eq!(profile.api_host_and_token(false, env_host.clone(), env_token.clone()).unwrap(), (env_host.clone(), env_token.clone()));
+        assert_eq!(profile.api_host_and_token(false, env_host.clone(), None).unwrap(), (env_host.clone(), None));
+        assert_eq!(profile.api_host_and_token(false, None, None).unwrap(), (profile.api_host.clone(), profile.api_token.clone()));
+        assert_eq!(profile.api_host_and_token(false, None, env_token.clone()).unwrap(), (profile.api_host.clone(), profile.api_token.clone()));
+        // selected profile: host and token always come together
+        assert_eq!(profile.api_host_and_token(true, None, env_token.clone()).unwrap(), (profile.api_host.clone(), profile.api_token.clone()));
+        assert_eq!(profile.api_host_and_token(true, profile.api_host.clone(), None).unwrap(), (profile.api_host.clone(), profile.api_token.clone()));
+        assert!(profile.api_host_and_token(true, env_host.clone(), env_token.clone()).is_err());
+        // profile without a host
+        assert_eq!(Profile::default().api_host_and_token(true, None, env_token.clone()).unwrap(), (None, env_token));
+    }
+
+    #[test]
+    fn test_api_token_command() -> () {
+        let mut profile = Profile::default();
+        assert_eq!(profile.api_token().unwrap(), None);
+        profile.api_token_command = Some("echo ' AgEPZGV2 '".to_string());
+        assert_eq!(profile.api_token().unwrap(), Some("AgEPZGV2".to_string()));
+        profile.api_token_command = Some("false".to_string());
+        assert!(profile.api_token().is_err());
+    }
+}
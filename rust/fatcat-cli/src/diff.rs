@@ -0,0 +1,183 @@
+use std::io::Write;
+use anyhow::Result;
+use serde_json::{json, Value};
+use termcolor::{Color, ColorSpec, WriteColor};
+use fatcat_openapi::models;
+use crate::{ApiModelSer, EntityType, FatcatApiClient, Specifier};
+
+
+/// A single field-level difference between two JSON documents (eg, two versions of an entity).
+/// Paths are JSON Pointers (RFC 6901), like "/ext_ids/doi".
+#[derive(Debug, PartialEq, Clone)]
+pub enum FieldChange {
+    Added { path: String, value: Value },
+    Removed { path: String, value: Value },
+    Changed { path: String, old: Value, new: Value },
+}
+
+pub fn diff_values(old: &Value, new: &Value) -> Vec<FieldChange> {
+    let mut changes = vec![];
+    diff_values_at("", old, new, &mut changes);
+    changes
+}
+
+fn diff_values_at(path: &str, old: &Value, new: &Value, changes: &mut Vec<FieldChange>) {
+    match (old, new) {
+        (Value::Object(old_obj), Value::Object(new_obj)) => {
+            for (key, old_val) in old_obj {
+                let key_path = format!("{}/{}", path, escape_pointer(key));
+                match new_obj.get(key) {
+                    Some(new_val) => diff_values_at(&key_path, old_val, new_val, changes),
+                    None => changes.push(FieldChange::Removed { path: key_path, value: old_val.clone() }),
+                }
+            }
+            for (key, new_val) in new_obj {
+                if !old_obj.contains_key(key) {
+                    let key_path = format!("{}/{}", path, escape_pointer(key));
+                    changes.push(FieldChange::Added { path: key_path, value: new_val.clone() });
+                }
+            }
+        },
+        (Value::Array(old_list), Value::Array(new_list)) => {
+            for (i, (old_val, new_val)) in old_list.iter().zip(new_list.iter()).enumerate() {
+                diff_values_at(&format!("{}/{}", path, i), old_val, new_val, changes);
+            }
+            // remove from the end first, so indexes stay valid if applied in order
+            for i in (new_list.len()..old_list.len()).rev() {
+                changes.push(FieldChange::Removed { path: format!("{}/{}", path, i), value: old_list[i].clone() });
+            }
+            for (i, new_val) in new_list.iter().enumerate().skip(old_list.len()) {
+                changes.push(FieldChange::Added { path: format!("{}/{}", path, i), value: new_val.clone() });
+            }
+        },
+        (old, new) if old == new => (),
+        (old, new) => changes.push(FieldChange::Changed { path: path.to_string(), old: old.clone(), new: new.clone() }),
+    }
+}
+
+fn escape_pointer(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+/// Converts changes to an RFC 6902 JSON Patch document
+pub fn to_json_patch(changes: &[FieldChange]) -> Value {
+    Value::Array(changes.iter().map(|change| match change {
+        FieldChange::Added { path, value } => json!({"op": "add", "path": path, "value": value}),
+        FieldChange::Removed { path, .. } => json!({"op": "remove", "path": path}),
+        FieldChange::Changed { path, new, .. } => json!({"op": "replace", "path": path, "value": new}),
+    }).collect())
+}
+
+/// Prints changes in a unified-diff-like format, with color (if enabled on the output stream)
+pub fn print_diff(changes: &[FieldChange], out: &mut dyn WriteColor) -> Result<()> {
+    let mut color_removed = ColorSpec::new();
+    color_removed.set_fg(Some(Color::Red));
+    let mut color_added = ColorSpec::new();
+    color_added.set_fg(Some(Color::Green));
+    for change in changes {
+        match change {
+            FieldChange::Removed { path, value } => {
+                out.set_color(&color_removed)?;
+                writeln!(out, "- {}: {}", path, value)?;
+            },
+            FieldChange::Added { path, value } => {
+                out.set_color(&color_added)?;
+                writeln!(out, "+ {}: {}", path, value)?;
+            },
+            FieldChange::Changed { path, old, new } => {
+                out.set_color(&color_removed)?;
+                writeln!(out, "- {}: {}", path, old)?;
+                out.set_color(&color_added)?;
+                writeln!(out, "+ {}: {}", path, new)?;
+            },
+        }
+    }
+    out.reset()?;
+    Ok(())
+}
+
+/// Entity JSON with null values and per-revision metadata (ident, revision, etc) removed, for
+/// comparing the content of two versions of an entity
+pub fn entity_content(value: &Value) -> Value {
+    let mut content = strip_nulls(value);
+    if let Some(obj) = content.as_object_mut() {
+        for key in &["ident", "revision", "state", "redirect", "edit_extra"] {
+            obj.remove(*key);
+        }
+    }
+    content
+}
+
+/// Removes null values (which are the same as missing fields in API entities) recursively, from
+/// both objects and arrays
+pub(crate) fn strip_nulls(value: &Value) -> Value {
+    match value {
+        Value::Object(obj) => Value::Object(obj.iter()
+            .filter(|(_, v)| !v.is_null())
+            .map(|(k, v)| (k.clone(), strip_nulls(v)))
+            .collect()),
+        Value::Array(list) => Value::Array(list.iter().filter(|v| !v.is_null()).map(strip_nulls).collect()),
+        other => other.clone(),
+    }
+}
+
+/// Changes made by an edit: from the previous revision to the new one. Either may be missing (for
+/// creations and deletions), in which case it is treated as an empty entity.
+pub fn edit_diff(api_client: &mut FatcatApiClient, entity_type: EntityType, edit: &models::EntityEdit) -> Result<Vec<FieldChange>> {
+    let old = revision_content(api_client, entity_type, &edit.prev_revision)?;
+    let new = revision_content(api_client, entity_type, &edit.revision)?;
+    Ok(diff_values(&old, &new))
+}
+
+fn revision_content(api_client: &mut FatcatApiClient, entity_type: EntityType, rev_id: &Option<String>) -> Result<Value> {
+    match rev_id {
+        None => Ok(Value::Object(serde_json::Map::new())),
+        Some(rev_id) => {
+            let entity = Specifier::from_revision(entity_type, rev_id).get_from_api(api_client, None, None)?;
+            Ok(entity_content(&serde_json::from_str(&entity.to_json_string()?)?))
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_values() -> () {
+        let old = json!({"title": "Old Title", "ext_ids": {"doi": "10.123/abc"}, "contribs": [{"raw_name": "A"}, {"raw_name": "B"}]});
+        let new = json!({"title": "New Title", "ext_ids": {"doi": "10.123/abc", "a/b": "c"}, "contribs": [{"raw_name": "A"}], "volume": "12"});
+        assert_eq!(diff_values(&old, &old), vec![]);
+        assert_eq!(diff_values(&old, &new), vec![
+            FieldChange::Removed { path: "/contribs/1".to_string(), value: json!({"raw_name": "B"}) },
+            FieldChange::Added { path: "/ext_ids/a~1b".to_string(), value: json!("c") },
+            FieldChange::Changed { path: "/title".to_string(), old: json!("Old Title"), new: json!("New Title") },
+            FieldChange::Added { path: "/volume".to_string(), value: json!("12") },
+        ]);
+    }
+
+    #[test]
+    fn test_to_json_patch() -> () {
+        let old = json!({"title": "Old Title", "subtitle": "Sub", "refs": [1, 2, 3]});
+        let new = json!({"title": "New Title", "volume": "12", "refs": [1]});
+        assert_eq!(to_json_patch(&diff_values(&old, &new)), json!([
+            {"op": "remove", "path": "/refs/2"},
+            {"op": "remove", "path": "/refs/1"},
+            {"op": "remove", "path": "/subtitle"},
+            {"op": "replace", "path": "/title", "value": "New Title"},
+            {"op": "add", "path": "/volume", "value": "12"},
+        ]));
+    }
+
+    #[test]
+    fn test_entity_content() -> () {
+        let entity = json!({"ident": "aaaaaaaaaaaaarceaaaaaaaaai", "revision": "0a5bd3d8-3ee3-4b5f-9b1c-1e5ebd1d4c2b", "title": "Title", "subtitle": null, "ext_ids": {"doi": null}});
+        assert_eq!(entity_content(&entity), json!({"title": "Title", "ext_ids": {}}));
+    }
+
+    #[test]
+    fn test_strip_nulls() -> () {
+        let value = json!({"title": "a", "subtitle": null, "contribs": [{"raw_name": "b", "role": null}, null]});
+        assert_eq!(strip_nulls(&value), json!({"title": "a", "contribs": [{"raw_name": "b"}]}));
+    }
+}
@@ -4,7 +4,7 @@ use anyhow::{Result, anyhow};
 use lazy_static::lazy_static;
 use regex::Regex;
 use fatcat_openapi::models;
-use crate::Specifier;
+use crate::{EntityType, Specifier};
 
 
 #[derive(Debug, PartialEq, Clone)]
@@ -65,6 +65,7 @@ impl ApiEntityModel for models::ChangelogEntry{}
 pub trait ApiModelSer {
     fn to_json_string(&self) -> Result<String>;
     fn to_toml_string(&self) -> Result<String>;
+    fn to_json_value(&self) -> Result<serde_json::Value>;
 }
 
 impl<T: serde::Serialize> ApiModelSer for T {
@@ -76,27 +77,36 @@ impl<T: serde::Serialize> ApiModelSer for T {
     fn to_toml_string(&self) -> Result<String> {
         Ok(toml::Value::try_from(self)?.to_string())
     }
+
+    fn to_json_value(&self) -> Result<serde_json::Value> {
+        Ok(serde_json::to_value(self)?)
+    }
 }
 
 pub trait ApiModelIdent {
     fn specifier(&self) -> Specifier;
 }
 
+// entities fetched by revision (instead of by ident) don't have an ident
 macro_rules! generic_entity_specifier {
-    ($specifier_type:ident) => {
+    ($specifier_type:ident, $revision_type:ident) => {
         fn specifier(&self) -> Specifier {
-            if let Some(fcid) = &self.ident { Specifier::$specifier_type(fcid.to_string()) } else { panic!("expected full entity") }
+            match (&self.ident, &self.revision) {
+                (Some(fcid), _) => Specifier::$specifier_type(fcid.to_string()),
+                (None, Some(rev)) => Specifier::$revision_type(rev.to_string()),
+                (None, None) => panic!("expected full entity"),
+            }
         }
     }
 }
 
-impl ApiModelIdent for models::ReleaseEntity { generic_entity_specifier!(Release); }
-impl ApiModelIdent for models::ContainerEntity { generic_entity_specifier!(Container); }
-impl ApiModelIdent for models::CreatorEntity { generic_entity_specifier!(Creator); }
-impl ApiModelIdent for models::WorkEntity { generic_entity_specifier!(Work); }
-impl ApiModelIdent for models::FileEntity { generic_entity_specifier!(File); }
-impl ApiModelIdent for models::FilesetEntity { generic_entity_specifier!(FileSet); }
-impl ApiModelIdent for models::WebcaptureEntity { generic_entity_specifier!(WebCapture); }
+impl ApiModelIdent for models::ReleaseEntity { generic_entity_specifier!(Release, ReleaseRevision); }
+impl ApiModelIdent for models::ContainerEntity { generic_entity_specifier!(Container, ContainerRevision); }
+impl ApiModelIdent for models::CreatorEntity { generic_entity_specifier!(Creator, CreatorRevision); }
+impl ApiModelIdent for models::WorkEntity { generic_entity_specifier!(Work, WorkRevision); }
+impl ApiModelIdent for models::FileEntity { generic_entity_specifier!(File, FileRevision); }
+impl ApiModelIdent for models::FilesetEntity { generic_entity_specifier!(FileSet, FileSetRevision); }
+impl ApiModelIdent for models::WebcaptureEntity { generic_entity_specifier!(WebCapture, WebCaptureRevision); }
 
 impl ApiModelIdent for models::ChangelogEntry{
     fn specifier(&self) -> Specifier {
@@ -120,6 +130,21 @@ pub trait ApiModelMutate {
     fn mutate(&mut self, mutations: Vec<Mutation>) -> Result<()>;
 }
 
+/// Checks that mutations can be applied to entities of the given type, by applying them to an
+/// empty entity. Useful before starting on a batch of edits.
+pub fn check_mutations(entity_type: EntityType, mutations: &[Mutation]) -> Result<()> {
+    let mut entity: Box<dyn ApiModelMutate> = match entity_type {
+        EntityType::Release => Box::new(models::ReleaseEntity::new(models::ReleaseExtIds::new())),
+        EntityType::Work => Box::new(models::WorkEntity::new()),
+        EntityType::Container => Box::new(models::ContainerEntity::new()),
+        EntityType::Creator => Box::new(models::CreatorEntity::new()),
+        EntityType::File => Box::new(models::FileEntity::new()),
+        EntityType::FileSet => Box::new(models::FilesetEntity::new()),
+        EntityType::WebCapture => Box::new(models::WebcaptureEntity::new()),
+    };
+    entity.mutate(mutations.to_vec())
+}
+
 impl ApiModelMutate for models::ReleaseEntity {
     fn mutate(&mut self, mutations: Vec<Mutation>) -> Result<()> {
         for m in mutations {
@@ -137,7 +162,7 @@ impl ApiModelMutate for models::ReleaseEntity {
                 ("number", val) => { self.number = val; },
                 ("publisher", val) => { self.publisher = val; },
                 ("language", val) => { self.language = val; },
-                (field, _) => unimplemented!("setting field {} on a release", field),
+                (field, _) => return Err(anyhow!("setting field {} on a release is not supported", field)),
             }
         }
         Ok(())
@@ -152,7 +177,7 @@ impl ApiModelMutate for models::ContainerEntity {
                 ("container_type", val) => { self.container_type = val; },
                 ("publisher", val) => { self.publisher = val; },
                 ("issnl", val) => { self.issnl = val; },
-                (field, _) => unimplemented!("setting field {} on a container", field),
+                (field, _) => return Err(anyhow!("setting field {} on a container is not supported", field)),
             }
         }
         Ok(())
@@ -166,7 +191,7 @@ impl ApiModelMutate for models::CreatorEntity {
                 ("display_name", val) => { self.display_name = val; },
                 ("given_name", val) => { self.given_name = val; },
                 ("surname", val) => { self.surname = val; },
-                (field, _) => unimplemented!("setting field {} on a creator", field),
+                (field, _) => return Err(anyhow!("setting field {} on a creator is not supported", field)),
             }
         }
         Ok(())
@@ -174,8 +199,11 @@ impl ApiModelMutate for models::CreatorEntity {
 }
 
 impl ApiModelMutate for models::WorkEntity {
-    fn mutate(&mut self, _mutations: Vec<Mutation>) -> Result<()> {
-        unimplemented!("mutations")
+    fn mutate(&mut self, mutations: Vec<Mutation>) -> Result<()> {
+        match mutations.first() {
+            Some(m) => Err(anyhow!("setting field {} on works is not supported", m.field)),
+            None => Ok(()),
+        }
     }
 }
 
@@ -189,7 +217,7 @@ impl ApiModelMutate for models::FileEntity {
                 ("sha1", val) => { self.sha1 = val; },
                 ("sha256", val) => { self.sha256 = val; },
                 ("mimetype", val) => { self.mimetype = val; },
-                (field, _) => unimplemented!("setting field {} on a file", field),
+                (field, _) => return Err(anyhow!("setting field {} on a file is not supported", field)),
             }
         }
         Ok(())
@@ -197,14 +225,20 @@ impl ApiModelMutate for models::FileEntity {
 }
 
 impl ApiModelMutate for models::FilesetEntity {
-    fn mutate(&mut self, _mutations: Vec<Mutation>) -> Result<()> {
-        unimplemented!("mutations")
+    fn mutate(&mut self, mutations: Vec<Mutation>) -> Result<()> {
+        match mutations.first() {
+            Some(m) => Err(anyhow!("setting field {} on filesets is not supported", m.field)),
+            None => Ok(()),
+        }
     }
 }
 
 impl ApiModelMutate for models::WebcaptureEntity {
-    fn mutate(&mut self, _mutations: Vec<Mutation>) -> Result<()> {
-        unimplemented!("mutations")
+    fn mutate(&mut self, mutations: Vec<Mutation>) -> Result<()> {
+        match mutations.first() {
+            Some(m) => Err(anyhow!("setting field {} on webcaptures is not supported", m.field)),
+            None => Ok(()),
+        }
     }
 }
 
@@ -213,7 +247,7 @@ impl ApiModelMutate for models::Editor {
         for m in mutations {
             match (m.field.as_str(), m.value) {
                 ("username", Some(val)) => { self.username = val; },
-                (field, _) => unimplemented!("setting field {} on an editor", field),
+                (field, _) => return Err(anyhow!("setting field {} on an editor is not supported", field)),
             }
         }
         Ok(())
@@ -225,7 +259,7 @@ impl ApiModelMutate for models::Editgroup {
         for m in mutations {
             match (m.field.as_str(), m.value) {
                 ("description", val) => { self.description = val; },
-                (field, _) => unimplemented!("setting field {} on an editgroup", field),
+                (field, _) => return Err(anyhow!("setting field {} on an editgroup is not supported", field)),
             }
         }
         Ok(())
@@ -233,8 +267,11 @@ impl ApiModelMutate for models::Editgroup {
 }
 
 impl ApiModelMutate for models::ChangelogEntry {
-    fn mutate(&mut self, _mutations: Vec<Mutation>) -> Result<()> {
-        unimplemented!("mutations")
+    fn mutate(&mut self, mutations: Vec<Mutation>) -> Result<()> {
+        match mutations.first() {
+            Some(m) => Err(anyhow!("setting field {} on changelog entries is not supported", m.field)),
+            None => Ok(()),
+        }
     }
 }
 
@@ -253,4 +290,14 @@ mod tests {
             Mutation { field: "title".to_string(), value: Some("string with spaces and stuff".to_string()) });
     }
 
+    #[test]
+    fn test_check_mutations() -> () {
+        let mutations = vec![Mutation::from_str("release_stage=published").unwrap(), Mutation::from_str("volume=").unwrap()];
+        assert!(check_mutations(EntityType::Release, &mutations).is_ok());
+        assert!(check_mutations(EntityType::Container, &mutations).is_err());
+        assert!(check_mutations(EntityType::Work, &mutations).is_err());
+        assert!(check_mutations(EntityType::Work, &[]).is_ok());
+        assert!(check_mutations(EntityType::File, &[Mutation::from_str("size=lots").unwrap()]).is_err());
+    }
+
 }
@@ -0,0 +1,184 @@
+
+use anyhow::Result;
+use serde_json::Value;
+use termcolor::{Color, ColorSpec, WriteColor};
+
+
+fn key_color() -> ColorSpec {
+    let mut color = ColorSpec::new();
+    color.set_fg(Some(Color::Blue)).set_bold(true);
+    color
+}
+
+fn header_color() -> ColorSpec {
+    let mut color = ColorSpec::new();
+    color.set_fg(Some(Color::Magenta)).set_bold(true);
+    color
+}
+
+fn string_color() -> ColorSpec {
+    let mut color = ColorSpec::new();
+    color.set_fg(Some(Color::Green));
+    color
+}
+
+fn number_color() -> ColorSpec {
+    let mut color = ColorSpec::new();
+    color.set_fg(Some(Color::Cyan));
+    color
+}
+
+/// For true, false, and null
+fn literal_color() -> ColorSpec {
+    let mut color = ColorSpec::new();
+    color.set_fg(Some(Color::Yellow));
+    color
+}
+
+fn write_colored<W: WriteColor>(out: &mut W, color: &ColorSpec, text: &str) -> Result<()> {
+    out.set_color(color)?;
+    write!(out, "{}", text)?;
+    out.reset()?;
+    Ok(())
+}
+
+/// Writes a JSON value with syntax coloring (no trailing newline).
+///
+/// Layout is the same as `serde_json::to_string()` (or `to_string_pretty()` if `pretty`), so with
+/// a non-coloring writer the output is identical.
+pub fn highlight_json<W: WriteColor>(out: &mut W, value: &Value, pretty: bool) -> Result<()> {
+    write_json_value(out, value, pretty, 0)
+}
+
+fn write_json_value<W: WriteColor>(out: &mut W, value: &Value, pretty: bool, depth: usize) -> Result<()> {
+    match value {
+        Value::Null | Value::Bool(_) => write_colored(out, &literal_color(), &value.to_string())?,
+        Value::Number(_) => write_colored(out, &number_color(), &value.to_string())?,
+        Value::String(_) => write_colored(out, &string_color(), &value.to_string())?,
+        Value::Array(list) if list.is_empty() => write!(out, "[]")?,
+        Value::Array(list) => {
+            write!(out, "[")?;
+            for (i, item) in list.iter().enumerate() {
+                if i > 0 {
+                    write!(out, ",")?;
+                }
+                write_indent(out, pretty, depth + 1)?;
+                write_json_value(out, item, pretty, depth + 1)?;
+            }
+            write_indent(out, pretty, depth)?;
+            write!(out, "]")?;
+        },
+        Value::Object(map) if map.is_empty() => write!(out, "{{}}")?,
+        Value::Object(map) => {
+            write!(out, "{{")?;
+            for (i, (key, val)) in map.iter().enumerate() {
+                if i > 0 {
+                    write!(out, ",")?;
+                }
+                write_indent(out, pretty, depth + 1)?;
+                write_colored(out, &key_color(), &serde_json::to_string(key)?)?;
+                write!(out, "{}", if pretty { ": " } else { ":" })?;
+                write_json_value(out, val, pretty, depth + 1)?;
+            }
+            write_indent(out, pretty, depth)?;
+            write!(out, "}}")?;
+        },
+    };
+    Ok(())
+}
+
+fn write_indent<W: WriteColor>(out: &mut W, pretty: bool, depth: usize) -> Result<()> {
+    if pretty {
+        write!(out, "\n{}", "  ".repeat(depth))?;
+    }
+    Ok(())
+}
+
+/// Writes serialized TOML with syntax coloring.
+///
+/// This is line-based, and relies on the regular layout of `toml` crate output: table headers on
+/// their own line, and one "key = value" per line. Inline arrays and tables are not colored.
+pub fn highlight_toml<W: WriteColor>(out: &mut W, toml_str: &str) -> Result<()> {
+    // closing delimiter, while inside a multi-line string
+    let mut multiline: Option<&str> = None;
+    for line in toml_str.lines() {
+        if let Some(delim) = multiline {
+            write_colored(out, &string_color(), line)?;
+            if line.contains(delim) {
+                multiline = None;
+            }
+        } else if line.starts_with('[') {
+            write_colored(out, &header_color(), line)?;
+        } else if let Some(idx) = line.find(" = ") {
+            let (key, val) = (&line[..idx], &line[idx + 3..]);
+            write_colored(out, &key_color(), key)?;
+            write!(out, " = ")?;
+            match val.chars().next() {
+                Some('"') | Some('\'') => {
+                    write_colored(out, &string_color(), val)?;
+                    for delim in ["\"\"\"", "'''"].iter() {
+                        if val.starts_with(delim) && !val[3..].contains(delim) {
+                            multiline = Some(*delim);
+                        }
+                    }
+                },
+                Some('t') | Some('f') => write_colored(out, &literal_color(), val)?,
+                Some(c) if c.is_ascii_digit() || c == '-' || c == '+' => write_colored(out, &number_color(), val)?,
+                _ => write!(out, "{}", val)?,
+            }
+        } else {
+            write!(out, "{}", line)?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use termcolor::{Buffer, NoColor};
+
+    #[test]
+    fn test_highlight_json() -> () {
+        let value = json!({
+            "title": "Some \"quoted\" title",
+            "release_year": 2020,
+            "withdrawn_status": null,
+            "is_oa": true,
+            "contribs": [{"raw_name": "A. Author", "index": 0}, {}],
+            "refs": [],
+        });
+        for pretty in [false, true].iter() {
+            let mut out = NoColor::new(Vec::new());
+            highlight_json(&mut out, &value, *pretty).unwrap();
+            let expected = if *pretty { serde_json::to_string_pretty(&value) } else { serde_json::to_string(&value) };
+            assert_eq!(String::from_utf8(out.into_inner()).unwrap(), expected.unwrap());
+        }
+
+        let mut out = Buffer::ansi();
+        highlight_json(&mut out, &value, true).unwrap();
+        let colored = String::from_utf8(out.into_inner()).unwrap();
+        assert!(colored.contains("\x1b["));
+        assert_ne!(colored, serde_json::to_string_pretty(&value).unwrap());
+    }
+
+    #[test]
+    fn test_highlight_toml() -> () {
+        let toml_str = "title = \"a = b\"\nrelease_year = 2020\nis_oa = false\nsubjects = [\"a\", \"b\"]\n\n[ext_ids]\ndoi = \"10.123/abc\"\n\n[[abstracts]]\ncontent = '''\n[not a header]\nkey = val'''\n";
+        let mut out = NoColor::new(Vec::new());
+        highlight_toml(&mut out, toml_str).unwrap();
+        assert_eq!(String::from_utf8(out.into_inner()).unwrap(), toml_str);
+
+        let mut out = Buffer::ansi();
+        highlight_toml(&mut out, toml_str).unwrap();
+        let colored = String::from_utf8(out.into_inner()).unwrap();
+        assert!(colored.contains("\x1b["));
+        // lines inside the multi-line string are colored as string, not as a header or key
+        let mut out = Buffer::ansi();
+        highlight_toml(&mut out, "key = val'''").unwrap();
+        let line_only = String::from_utf8(out.into_inner()).unwrap();
+        assert!(!colored.contains(&line_only));
+    }
+}
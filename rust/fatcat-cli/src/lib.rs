@@ -1,14 +1,13 @@
 
 use std::io::Read;
-use std::path::PathBuf;
-use std::io::BufRead;
-use tabwriter::TabWriter;
+use std::path::{Path, PathBuf};
 use chrono_humanize::HumanTime;
 use anyhow::{Result, anyhow, Context};
 use std::io::Write;
-use termcolor::{ColorChoice, StandardStream, Color, ColorSpec, WriteColor};
+use termcolor::{StandardStream, Color, ColorSpec, WriteColor};
 use data_encoding::BASE64;
-use macaroon::{Macaroon, Verifier};
+use macaroon::Macaroon;
+use chrono::{DateTime, Utc};
 use fatcat_openapi::models;
 #[allow(unused_imports)]
 use log::{self,info,debug};
@@ -18,11 +17,22 @@ mod entities;
 mod search;
 mod specifier;
 mod api;
+mod diff;
+mod state;
+mod config;
+mod output;
+mod highlight;
+mod citation;
 
-pub use entities::{ApiEntityModel,ApiModelSer,ApiModelIdent,Mutation};
-pub use specifier::Specifier;
-pub use api::FatcatApiClient;
-pub use search::crude_search;
+pub use entities::{ApiEntityModel,ApiModelSer,ApiModelIdent,Mutation,check_mutations};
+pub use citation::ApiModelCite;
+pub use specifier::{EditgroupSpecifier, Specifier};
+pub use api::{EditgroupBatcher, FatcatApiClient, UpdateResult, editgroup_edits_of_type};
+pub use diff::{FieldChange, diff_values, edit_diff, entity_content, print_diff, to_json_patch};
+pub use search::{AggBucket, SearchAgg, SearchAggResult, SearchFilter, SearchIndex, SearchQuery, SearchResults, SearchSort, YearRange, crude_search};
+pub use state::{CliState, config_dir};
+pub use config::{CliConfig, Profile, CONFIG_TEMPLATE};
+pub use output::{ColorMode, OutputFormat, RecordPrinter, color_choice, set_color_mode, entity_columns, table_writer, CHANGELOG_COLUMNS, EDIT_COLUMNS, EDITGROUP_COLUMNS};
 
 // Want to show:
 // - whether api_token found
@@ -36,19 +46,15 @@ pub struct ClientStatus {
     pub api_host: String,
     pub last_changelog: Option<i64>,
     pub account: Option<models::Editor>,
+    pub current_editgroup: Option<String>,
+    pub token_expires: Option<DateTime<Utc>>,
 }
 
 impl ClientStatus {
 
     pub fn pretty_print(self) -> Result<()> {
 
-        let mut color_stdout = StandardStream::stdout(
-            if atty::is(atty::Stream::Stdout) {
-                ColorChoice::Auto
-            } else {
-                ColorChoice::Never
-            }
-        );
+        let mut color_stdout = StandardStream::stdout(color_choice(atty::Stream::Stdout));
         let color_normal = ColorSpec::new();
         let mut color_bold = ColorSpec::new();
         color_bold.set_bold(true);
@@ -79,7 +85,18 @@ impl ClientStatus {
         write!(&mut color_stdout, "{:>16}: ", "API auth token")?;
         if self.has_api_token {
             color_stdout.set_color(&color_happy)?;
-            writeln!(&mut color_stdout, "[configured]")?;
+            write!(&mut color_stdout, "[configured]")?;
+            match self.token_expires {
+                Some(expires) if expires <= Utc::now() => {
+                    color_stdout.set_color(&color_sad)?;
+                    writeln!(&mut color_stdout, " [expired {}]", HumanTime::from(expires))?;
+                },
+                Some(expires) => {
+                    color_stdout.set_color(&color_normal)?;
+                    writeln!(&mut color_stdout, " (expires {})", HumanTime::from(expires))?;
+                },
+                None => writeln!(&mut color_stdout)?,
+            };
         } else {
             color_stdout.set_color(&color_sad)?;
             writeln!(&mut color_stdout, "[not configured]")?;
@@ -111,6 +128,15 @@ impl ClientStatus {
             writeln!(&mut color_stdout, "{:>16}  editor_{}", "", editor.editor_id.unwrap())?;
         };
         color_stdout.set_color(&color_normal)?;
+        write!(&mut color_stdout, "{:>16}: ", "Editgroup")?;
+        match self.current_editgroup {
+            Some(eg_id) => {
+                color_stdout.set_color(&color_bold)?;
+                writeln!(&mut color_stdout, "editgroup_{}", eg_id)?;
+            },
+            None => writeln!(&mut color_stdout, "[none]")?,
+        };
+        color_stdout.set_color(&color_normal)?;
         Ok(())
     }
 }
@@ -126,6 +152,33 @@ pub enum EntityType {
     WebCapture,
 }
 
+impl EntityType {
+    pub const ALL: [EntityType; 7] = [
+        EntityType::Release,
+        EntityType::Work,
+        EntityType::Container,
+        EntityType::Creator,
+        EntityType::File,
+        EntityType::FileSet,
+        EntityType::WebCapture,
+    ];
+}
+
+impl std::fmt::Display for EntityType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let s = match self {
+            EntityType::Release => "release",
+            EntityType::Work => "work",
+            EntityType::Container => "container",
+            EntityType::Creator => "creator",
+            EntityType::File => "file",
+            EntityType::FileSet => "fileset",
+            EntityType::WebCapture => "webcapture",
+        };
+        write!(f, "{}", s)
+    }
+}
+
 impl FromStr for EntityType {
     type Err = anyhow::Error;
 
@@ -143,75 +196,354 @@ impl FromStr for EntityType {
     }
 }
 
-/// Takes a macaroon token (as base64-encoded string) and tries to parse out an editor id
-pub fn parse_macaroon_editor_id(s: &str) -> Result<String> {
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum InputFormat {
+    Json,
+    Toml,
+}
+
+impl InputFormat {
+    /// Guesses format from file extension, defaulting to JSON
+    pub fn from_path(path: &Path) -> InputFormat {
+        match path.extension().and_then(|v| v.to_str()) {
+            Some("toml") => InputFormat::Toml,
+            _ => InputFormat::Json,
+        }
+    }
+}
+
+impl FromStr for InputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" | "jsonl" => Ok(InputFormat::Json),
+            "toml" => Ok(InputFormat::Toml),
+            _ => Err(anyhow!("invalid input format: {}", s)),
+        }
+    }
+}
+
+/// Caveats of a macaroon API token. The signature is not (and can not be) verified client-side.
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct TokenInfo {
+    pub editor_id: Option<String>,
+    /// From a "time > ..." caveat
+    pub created: Option<DateTime<Utc>>,
+    /// From a "time < ..." caveat
+    pub expires: Option<DateTime<Utc>>,
+    /// All first-party caveat predicates, in order
+    pub caveats: Vec<String>,
+}
+
+impl TokenInfo {
+
+    pub fn from_caveats(caveats: Vec<String>) -> Self {
+        let parse_time = |v: &str| DateTime::parse_from_rfc3339(v.trim()).ok().map(|dt| dt.with_timezone(&Utc));
+        let mut info = TokenInfo {
+            editor_id: None,
+            created: None,
+            expires: None,
+            caveats: vec![],
+        };
+        for predicate in caveats.iter() {
+            if let Some(editor_id) = predicate.strip_prefix("editor_id = ") {
+                info.editor_id = info.editor_id.or_else(|| Some(editor_id.to_string()));
+            } else if let Some(created) = predicate.strip_prefix("time > ") {
+                info.created = parse_time(created);
+            } else if let Some(expires) = predicate.strip_prefix("time < ") {
+                // if there are multiple expiry caveats, the earliest one wins
+                info.expires = match (info.expires, parse_time(expires)) {
+                    (Some(a), Some(b)) => Some(std::cmp::min(a, b)),
+                    (a, b) => a.or(b),
+                };
+            }
+        }
+        info.caveats = caveats;
+        info
+    }
+
+    pub fn is_expired(&self) -> bool {
+        match self.expires {
+            Some(expires) => expires <= Utc::now(),
+            None => false,
+        }
+    }
+
+    pub fn pretty_print(&self) -> Result<()> {
+
+        let mut color_stdout = StandardStream::stdout(color_choice(atty::Stream::Stdout));
+        let color_normal = ColorSpec::new();
+        let mut color_bold = ColorSpec::new();
+        color_bold.set_bold(true);
+        let mut color_sad = ColorSpec::new();
+        color_sad.set_fg(Some(Color::Red)).set_bold(true);
+
+        color_stdout.set_color(&color_normal)?;
+        write!(&mut color_stdout, "{:>16}: ", "Editor")?;
+        color_stdout.set_color(&color_bold)?;
+        match self.editor_id {
+            Some(ref editor_id) => writeln!(&mut color_stdout, "editor_{}", editor_id)?,
+            None => writeln!(&mut color_stdout, "[none]")?,
+        };
+        color_stdout.set_color(&color_normal)?;
+        write!(&mut color_stdout, "{:>16}: ", "Created")?;
+        match self.created {
+            Some(created) => writeln!(&mut color_stdout, "{} ({})", created.to_rfc3339(), HumanTime::from(created))?,
+            None => writeln!(&mut color_stdout, "-")?,
+        };
+        write!(&mut color_stdout, "{:>16}: ", "Expires")?;
+        match self.expires {
+            Some(expires) => {
+                write!(&mut color_stdout, "{} ({})", expires.to_rfc3339(), HumanTime::from(expires))?;
+                if self.is_expired() {
+                    color_stdout.set_color(&color_sad)?;
+                    write!(&mut color_stdout, " [expired]")?;
+                    color_stdout.set_color(&color_normal)?;
+                }
+                writeln!(&mut color_stdout)?;
+            },
+            None => writeln!(&mut color_stdout, "[never]")?,
+        };
+        for (i, caveat) in self.caveats.iter().enumerate() {
+            writeln!(&mut color_stdout, "{:>16}{} {}", if i == 0 { "Caveats" } else { "" }, if i == 0 { ":" } else { " " }, caveat)?;
+        }
+        Ok(())
+    }
+}
+
+/// Takes a macaroon token (as base64-encoded string) and decodes the caveats
+pub fn parse_macaroon_token(s: &str) -> Result<TokenInfo> {
     let raw = BASE64.decode(s.as_bytes()).context("macaroon parsing failed")?;
     let mac = Macaroon::deserialize(&raw).map_err(|err| anyhow!("macaroon deserialization failed: {:?}", err))?;
     let mac = mac.validate().map_err(|err| anyhow!("macaroon validation failed: {:?}", err))?;
-    let mut verifier = Verifier::new();
-    let mut editor_id: Option<String> = None;
-    for caveat in mac.first_party_caveats() {
-        if caveat.predicate().starts_with("editor_id = ") {
-            editor_id = Some(caveat.predicate().get(12..).context("parsing macaroon")?.to_string());
-            break;
-        }
+    let caveats = mac.first_party_caveats().iter().map(|caveat| caveat.predicate().to_string()).collect();
+    Ok(TokenInfo::from_caveats(caveats))
+}
+
+/// Takes a macaroon token (as base64-encoded string) and tries to parse out an editor id
+pub fn parse_macaroon_editor_id(s: &str) -> Result<String> {
+    match parse_macaroon_token(s)?.editor_id {
+        Some(id) => Ok(id),
+        None => Err(anyhow!("expected an editor_id caveat in macaroon token")),
     }
-    let editor_id = match editor_id {
-        Some(id) => id,
-        None => return Err(anyhow!("expected an editor_id caveat in macaroon token")),
-    };
-    verifier.satisfy_exact(&format!("editor_id = {}", editor_id.to_string()));
-    Ok(editor_id)
 }
 
-pub fn print_editgroups(eg_list: Vec<models::Editgroup>, json: bool) -> Result<()> {
-    if json {
-        for eg in eg_list {
-            writeln!(&mut std::io::stdout(), "{}", eg.to_json_string()?)?;
-        }
-    } else {
-        let mut tw = TabWriter::new(std::io::stdout());
-        writeln!(tw, "editgroup_id\tchangelog_index\tcreated\tsubmitted\tdescription")?;
-        for eg in eg_list {
-            writeln!(tw, "{}\t{}\t{}\t{}\t{}",
-                eg.editgroup_id.unwrap(),
-                eg.changelog_index.map_or("-".to_string(), |v| v.to_string()),
-                eg.created.map_or("-".to_string(), |v| HumanTime::from(v).to_string()),
-                eg.submitted.map_or("-".to_string(), |v| HumanTime::from(v).to_string()),
-                eg.description.unwrap_or_else(|| "-".to_string()))?;
+/// Prints a sequence of objects with `RecordPrinter` (for formats other than table and TSV)
+fn print_records<T: serde::Serialize>(records: Vec<T>, output: OutputFormat) -> Result<()> {
+    let mut printer = RecordPrinter::new(output, &[]);
+    for record in records {
+        printer.print(&record)?;
+    }
+    printer.finish()
+}
+
+/// Timestamps are relative ("3 days ago") in tables, but exact in TSV
+fn format_timestamp(ts: DateTime<Utc>, output: OutputFormat) -> String {
+    match output {
+        OutputFormat::Tsv => ts.to_rfc3339(),
+        _ => HumanTime::from(ts).to_string(),
+    }
+}
+
+pub fn print_editgroups(eg_list: Vec<models::Editgroup>, output: OutputFormat) -> Result<()> {
+    if output != OutputFormat::Table && output != OutputFormat::Tsv {
+        return print_records(eg_list, output);
+    }
+    let mut tw = table_writer(output);
+    writeln!(tw, "editgroup_id\tchangelog_index\tcreated\tsubmitted\tdescription")?;
+    for eg in eg_list {
+        writeln!(tw, "{}\t{}\t{}\t{}\t{}",
+            eg.editgroup_id.unwrap(),
+            eg.changelog_index.map_or("-".to_string(), |v| v.to_string()),
+            eg.created.map_or("-".to_string(), |v| format_timestamp(v, output)),
+            eg.submitted.map_or("-".to_string(), |v| format_timestamp(v, output)),
+            eg.description.unwrap_or_else(|| "-".to_string()))?;
+    }
+    tw.flush()?;
+    Ok(())
+}
+
+/// Table (or TSV) of all the edits in an editgroup
+pub fn print_editgroup_edits(eg: &models::Editgroup, output: OutputFormat) -> Result<()> {
+    let mut tw = table_writer(output);
+    writeln!(tw, "entity_type\tident\tprev_revision\trevision\tredirect_ident")?;
+    if let Some(edits) = &eg.edits {
+        for entity_type in EntityType::ALL.iter() {
+            for ee in editgroup_edits_of_type(edits, *entity_type) {
+                writeln!(tw, "{}\t{}\t{}\t{}\t{}",
+                    entity_type,
+                    ee.ident,
+                    ee.prev_revision.unwrap_or_else(|| "-".to_string()),
+                    ee.revision.unwrap_or_else(|| "-".to_string()),
+                    ee.redirect_ident.unwrap_or_else(|| "-".to_string()))?;
+            }
         }
-        tw.flush()?;
     }
+    tw.flush()?;
+    Ok(())
+}
+
+pub fn print_annotations(annotation_list: Vec<models::EditgroupAnnotation>, output: OutputFormat) -> Result<()> {
+    if output != OutputFormat::Table && output != OutputFormat::Tsv {
+        return print_records(annotation_list, output);
+    }
+    let mut tw = table_writer(output);
+    writeln!(tw, "editgroup_id\teditor\tcreated\tcomment")?;
+    for annotation in annotation_list {
+        let editor = match (annotation.editor, annotation.editor_id) {
+            (Some(editor), _) => editor.username,
+            (None, Some(editor_id)) => format!("editor_{}", editor_id),
+            (None, None) => "-".to_string(),
+        };
+        writeln!(tw, "{}\t{}\t{}\t{}",
+            annotation.editgroup_id.unwrap_or_else(|| "-".to_string()),
+            editor,
+            annotation.created.map_or("-".to_string(), |v| format_timestamp(v, output)),
+            // keep multi-line (markdown) comments to a single table row
+            annotation.comment_markdown.map_or("-".to_string(), |v| v.split_whitespace().collect::<Vec<&str>>().join(" ")))?;
+    }
+    tw.flush()?;
     Ok(())
 }
 
-pub fn read_entity_file(input_path: Option<PathBuf>) -> Result<String> {
+pub fn print_entity_histories(history_list: Vec<models::EntityHistoryEntry>, output: OutputFormat) -> Result<()> {
+    if output != OutputFormat::Table && output != OutputFormat::Tsv {
+        return print_records(history_list, output);
+    }
+    let mut tw = table_writer(output);
+    writeln!(tw, "changelog_index\teditgroup_id\teditor_id\ttimestamp\tdescription")?;
+    for history in history_list {
+        writeln!(tw, "{}\t{}\t{}\t{}\t{}",
+            history.changelog_entry.index,
+            history.editgroup.editgroup_id.unwrap_or_else(|| "-".to_string()),
+            history.editgroup.editor_id.unwrap_or_else(|| "-".to_string()),
+            format_timestamp(history.changelog_entry.timestamp, output),
+            history.editgroup.description.unwrap_or_else(|| "-".to_string()))?;
+    }
+    tw.flush()?;
+    Ok(())
+}
+
+pub fn print_changelog_entries(entry_list: Vec<models::ChangelogEntry>, output: OutputFormat, header: bool) -> Result<()> {
+    if output != OutputFormat::Table && output != OutputFormat::Tsv {
+        return print_records(entry_list, output);
+    }
+    let mut tw = table_writer(output);
+    if header {
+        writeln!(tw, "index\teditgroup_id\teditor_id\ttimestamp\tdescription")?;
+    }
+    for entry in entry_list {
+        let (editor_id, description) = match entry.editgroup {
+            Some(eg) => (eg.editor_id, eg.description),
+            None => (None, None),
+        };
+        writeln!(tw, "{}\t{}\t{}\t{}\t{}",
+            entry.index,
+            entry.editgroup_id,
+            editor_id.unwrap_or_else(|| "-".to_string()),
+            format_timestamp(entry.timestamp, output),
+            description.unwrap_or_else(|| "-".to_string()))?;
+    }
+    tw.flush()?;
+    Ok(())
+}
+
+/// Reads a single entity from a file (or stdin, if path is None or "-"), returning it as a JSON
+/// string. Input can be pretty-printed JSON, a single line of JSON, or TOML.
+pub fn read_entity_file(input_path: Option<PathBuf>, input_format: Option<InputFormat>) -> Result<String> {
+    let mut entities = read_entity_stream(input_path, input_format)?;
+    let json_str = match entities.next() {
+        Some(result) => result?,
+        None => return Err(anyhow!("no entity found in input")),
+    };
+    if entities.next().is_some() {
+        return Err(anyhow!("expected a single entity, but input contains multiple (use a *-multi command?)"));
+    }
+    Ok(json_str)
+}
+
+/// Reads any number of entities from a file (or stdin), returning them one at a time as JSON
+/// strings. JSON input can be JSON-lines, or any sequence of (possibly pretty-printed) JSON
+/// documents. TOML input is always a single entity.
+pub fn read_entity_stream(input_path: Option<PathBuf>, input_format: Option<InputFormat>) -> Result<Box<dyn Iterator<Item = Result<String>>>> {
     // treat "-" as "use stdin"
     let input_path = match input_path {
         Some(s) if s.to_string_lossy() == "-" => None,
         _ => input_path,
     };
-    match input_path {
-        None => {
-            let mut line = String::new();
-            std::io::stdin().read_line(&mut line)?;
-            Ok(line)
-        },
-        Some(path) if path.extension().map(|v| v.to_str()) == Some(Some("toml")) => {
-            info!("reading {:?} as TOML", path);
+    let input_format = match (input_format, &input_path) {
+        (Some(format), _) => format,
+        (None, Some(path)) => InputFormat::from_path(path),
+        (None, None) => InputFormat::Json,
+    };
+    let mut reader: Box<dyn Read> = match input_path {
+        None => Box::new(std::io::stdin()),
+        Some(path) => Box::new(std::fs::File::open(&path).with_context(|| format!("opening entity file {:?}", path))?),
+    };
+    match input_format {
+        InputFormat::Toml => {
+            info!("reading input as TOML");
             // as a hack, read TOML but then serialize it back to JSON
             let mut contents = String::new();
-            let mut input_file = std::fs::File::open(path).context("reading entity from TOML file")?;
-            input_file.read_to_string(&mut contents)?;
+            reader.read_to_string(&mut contents).context("reading entity from TOML file")?;
             let value: toml::Value = contents.parse().context("parsing TOML file")?;
-            Ok(serde_json::to_string(&value)?)
+            Ok(Box::new(std::iter::once(Ok(serde_json::to_string(&value)?))))
         },
-        Some(path) => {
-            let mut line = String::new();
-            let input_file = std::fs::File::open(path)?;
-            let mut buffered = std::io::BufReader::new(input_file);
-            buffered.read_line(&mut line)?;
-            Ok(line)
+        InputFormat::Json => {
+            let stream = serde_json::Deserializer::from_reader(std::io::BufReader::new(reader))
+                .into_iter::<serde_json::Value>()
+                .map(|result| Ok(serde_json::to_string(&result.context("parsing JSON input")?)?));
+            Ok(Box::new(stream))
         },
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_entity_stream() -> () {
+        let mut input_file = tempfile::Builder::new().suffix(".json").tempfile().unwrap();
+        writeln!(input_file, "{{\n  \"title\": \"first\",\n  \"volume\": \"12\"\n}}").unwrap();
+        let json_str = read_entity_file(Some(input_file.path().to_path_buf()), None).unwrap();
+        assert_eq!(json_str, r#"{"title":"first","volume":"12"}"#);
+
+        writeln!(input_file, "{{\"title\": \"second\"}}").unwrap();
+        writeln!(input_file, "{{\"title\": \"third\"}}").unwrap();
+        let entities: Vec<String> = read_entity_stream(Some(input_file.path().to_path_buf()), None).unwrap()
+            .collect::<Result<Vec<String>>>().unwrap();
+        assert_eq!(entities.len(), 3);
+        assert_eq!(entities[2], r#"{"title":"third"}"#);
+        assert!(read_entity_file(Some(input_file.path().to_path_buf()), None).is_err());
+    }
+
+    #[test]
+    fn test_input_format() -> () {
+        assert_eq!(InputFormat::from_str("jsonl").unwrap(), InputFormat::Json);
+        assert_eq!(InputFormat::from_str("toml").unwrap(), InputFormat::Toml);
+        assert!(InputFormat::from_str("yaml").is_err());
+        assert_eq!(InputFormat::from_path(std::path::Path::new("release.toml")), InputFormat::Toml);
+        assert_eq!(InputFormat::from_path(std::path::Path::new("releases.json")), InputFormat::Json);
+    }
+
+    #[test]
+    fn test_token_info() -> () {
+        let info = TokenInfo::from_caveats(vec![
+            "editor_id = aaaaaaaaaaaabkvkaaaaaaaaae".to_string(),
+            "time > 2020-01-01T00:00:00Z".to_string(),
+            "time < 2020-01-31T00:00:00Z".to_string(),
+        ]);
+        assert_eq!(info.editor_id, Some("aaaaaaaaaaaabkvkaaaaaaaaae".to_string()));
+        assert_eq!(info.created, Some(DateTime::parse_from_rfc3339("2020-01-01T00:00:00Z").unwrap().with_timezone(&Utc)));
+        assert_eq!(info.expires, Some(DateTime::parse_from_rfc3339("2020-01-31T00:00:00Z").unwrap().with_timezone(&Utc)));
+        assert_eq!(info.caveats.len(), 3);
+        assert!(info.is_expired());
+
+        let info = TokenInfo::from_caveats(vec!["editor_id = aaaaaaaaaaaabkvkaaaaaaaaae".to_string()]);
+        assert_eq!(info.expires, None);
+        assert!(!info.is_expired());
+        assert_eq!(TokenInfo::from_caveats(vec![]).editor_id, None);
+    }
+}
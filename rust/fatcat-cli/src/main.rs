@@ -1,11 +1,12 @@
 
 use std::path::PathBuf;
 use fatcat_cli::ApiModelSer;
-use std::io::Write;
-use termcolor::{ColorChoice, StandardStream, Color, ColorSpec, WriteColor};
+use std::io::{BufRead, Write};
+use std::str::FromStr;
+use termcolor::{StandardStream, Color, ColorSpec, WriteColor};
 use anyhow::{Result, Context, anyhow};
 #[allow(unused_imports)]
-use log::{self,info,debug};
+use log::{self,info,debug,warn};
 use structopt::StructOpt;
 use fatcat_cli::*;
 use fatcat_openapi::{client, models, ApiNoContext};
@@ -15,14 +16,30 @@ use fatcat_openapi::{client, models, ApiNoContext};
 #[structopt(rename_all = "kebab-case", about = "CLI interface to Fatcat API" )]
 struct Opt {
 
-    #[structopt(long = "--api-host", env = "FATCAT_API_HOST", default_value = "https://api.fatcat.wiki")]
-    api_host: String,
+    /// API server [default: https://api.fatcat.wiki]
+    #[structopt(long = "--api-host", env = "FATCAT_API_HOST")]
+    api_host: Option<String>,
 
     #[structopt(long = "--api-token", env = "FATCAT_API_AUTH_TOKEN", hide_env_values = true)]
     api_token: Option<String>,
 
-    #[structopt(long = "--search-host", env = "FATCAT_SEARCH_HOST", default_value = "https://search.fatcat.wiki")]
-    search_host: String,
+    /// Search server [default: https://search.fatcat.wiki]
+    #[structopt(long = "--search-host", env = "FATCAT_SEARCH_HOST")]
+    search_host: Option<String>,
+
+    /// Named profile from the config file (see `config show`)
+    #[structopt(long, env = "FATCAT_PROFILE")]
+    profile: Option<String>,
+
+    /// Output format: json, json-pretty, toml, yaml, table, tsv, es-json (search only), or
+    /// bibtex, csl-json, ris (releases only)
+    #[structopt(long, short = "o", env = "FATCAT_OUTPUT")]
+    output: Option<OutputFormat>,
+
+    /// Colored output: auto, always, or never. "auto" colors only when writing to a terminal,
+    /// and `NO_COLOR` is not set
+    #[structopt(long, default_value = "auto")]
+    color: ColorMode,
 
     /// Pass many times for more log output
     ///
@@ -35,11 +52,43 @@ struct Opt {
     cmd: Command,
 }
 
+#[derive(StructOpt)]
+enum ConfigCommand {
+    /// Print the config file, with API tokens redacted
+    Show,
+    /// Print the location of the config file
+    Path,
+    /// Open the config file in an editor, then check that it is valid
+    Edit {
+        #[structopt(long = "--editing-command", env = "EDITOR")]
+        editing_command: String,
+    },
+}
+
+#[derive(StructOpt)]
+enum AuthCommand {
+    /// Decode the API token and show all caveats, including expiry
+    Inspect {
+        #[structopt(long)]
+        json: bool,
+    },
+    /// Create a new API token (requires admin privileges)
+    CreateToken {
+        /// Editor to create the token for (default: the authenticated editor)
+        #[structopt(long = "--editor-id", short)]
+        editor_id: Option<String>,
+
+        /// Token lifetime, in seconds or with a unit suffix (like "90d"); default is set by the server
+        #[structopt(long, parse(try_from_str = parse_duration))]
+        duration: Option<i32>,
+    },
+}
+
 #[derive(StructOpt)]
 enum EditgroupCommand {
     Create {
         #[structopt(long, short)]
-        description: String,
+        description: Option<String>,
     },
     List {
         #[structopt(long = "--editor-id", short)]
@@ -58,17 +107,61 @@ enum EditgroupCommand {
         #[structopt(long)]
         json: bool,
     },
+    Show {
+        #[structopt(env = "FATCAT_EDITGROUP", hide_env_values = true)]
+        editgroup_id: Option<EditgroupSpecifier>,
+
+        /// Also fetch revisions and show field-level changes for every edit (table output only)
+        #[structopt(long)]
+        diff: bool,
+
+        #[structopt(long)]
+        json: bool,
+    },
+    Annotate {
+        #[structopt(env = "FATCAT_EDITGROUP", hide_env_values = true)]
+        editgroup_id: Option<EditgroupSpecifier>,
+
+        /// Comment text (markdown)
+        #[structopt(long, short)]
+        comment: String,
+
+        /// Extra metadata, as a JSON object
+        #[structopt(long)]
+        extra: Option<String>,
+    },
+    Annotations {
+        #[structopt(env = "FATCAT_EDITGROUP", hide_env_values = true)]
+        editgroup_id: Option<EditgroupSpecifier>,
+
+        #[structopt(long = "--editor-id", short)]
+        editor_id: Option<String>,
+
+        #[structopt(long, short = "-n", default_value = "20")]
+        limit: i64,
+
+        #[structopt(long)]
+        json: bool,
+    },
     Accept {
         #[structopt(env = "FATCAT_EDITGROUP", hide_env_values = true)]
-        editgroup_id: String,
+        editgroup_id: Option<EditgroupSpecifier>,
     },
     Submit {
         #[structopt(env = "FATCAT_EDITGROUP", hide_env_values = true)]
-        editgroup_id: String,
+        editgroup_id: Option<EditgroupSpecifier>,
     },
     Unsubmit {
         #[structopt(env = "FATCAT_EDITGROUP", hide_env_values = true)]
-        editgroup_id: String,
+        editgroup_id: Option<EditgroupSpecifier>,
+    },
+    Use {
+        /// Editgroup to use for later commands ("new", "auto", "last", or an id); "none" to clear
+        editgroup: String,
+
+        /// Description, if creating a new editgroup
+        #[structopt(long, short)]
+        description: Option<String>,
     },
 }
 
@@ -97,8 +190,34 @@ enum Command {
         #[structopt(long = "--file", short = "-f", parse(from_os_str))]
         input_path: Option<PathBuf>,
 
+        /// Input format ("json" or "toml"); default is based on file extension, or JSON.
+        #[structopt(long = "--input-format")]
+        input_format: Option<InputFormat>,
+
+        #[structopt(long = "--editgroup-id", short, env = "FATCAT_EDITGROUP", hide_env_values = true)]
+        editgroup_id: Option<EditgroupSpecifier>,
+    },
+    CreateMulti {
+        entity_type: EntityType,
+
+        /// Input file, "-" for stdin.
+        #[structopt(long = "--file", short = "-f", parse(from_os_str))]
+        input_path: Option<PathBuf>,
+
+        /// Number of entities per auto-accepted editgroup (with --auto-batch)
+        #[structopt(long = "--batch-size", default_value = "50", parse(try_from_str = parse_batch_size))]
+        batch_size: usize,
+
+        /// Create (and accept) a new editgroup for each batch, instead of using --editgroup-id
+        #[structopt(long = "--auto-batch")]
+        auto_batch: bool,
+
+        /// Editgroup description (with --auto-batch)
+        #[structopt(long, short)]
+        description: Option<String>,
+
         #[structopt(long = "--editgroup-id", short, env = "FATCAT_EDITGROUP", hide_env_values = true)]
-        editgroup_id: String,
+        editgroup_id: Option<EditgroupSpecifier>,
     },
     Update {
         specifier: Specifier,
@@ -107,39 +226,190 @@ enum Command {
         #[structopt(long = "--file", short = "-f", parse(from_os_str))]
         input_path: Option<PathBuf>,
 
+        /// Input format ("json" or "toml"); default is based on file extension, or JSON.
+        #[structopt(long = "--input-format")]
+        input_format: Option<InputFormat>,
+
         #[structopt(long = "--editgroup-id", short, env = "FATCAT_EDITGROUP", hide_env_values = true)]
-        editgroup_id: String,
+        editgroup_id: Option<EditgroupSpecifier>,
+
+        /// Start from this entity or revision (eg, "release_rev:<uuid>") instead of the current
+        /// version; useful for restoring an old revision.
+        #[structopt(long = "--base")]
+        base: Option<Specifier>,
+
+        /// Update even if the entity has been edited since the input was fetched
+        #[structopt(long)]
+        force: bool,
 
         mutations: Vec<Mutation>,
     },
+    UpdateMulti {
+        entity_type: EntityType,
+
+        /// Input file (full entities, including ident and revision), "-" for stdin.
+        #[structopt(long = "--file", short = "-f", parse(from_os_str))]
+        input_path: Option<PathBuf>,
+
+        /// Number of edits per editgroup; a new editgroup is created when one is full
+        #[structopt(long = "--batch-size", default_value = "50", parse(try_from_str = parse_batch_size))]
+        batch_size: usize,
+
+        /// Description for any newly created editgroups
+        #[structopt(long, short)]
+        description: Option<String>,
+
+        /// Editgroup for the first batch of edits; a new editgroup is created if not provided
+        #[structopt(long = "--editgroup-id", short, env = "FATCAT_EDITGROUP", hide_env_values = true)]
+        editgroup_id: Option<EditgroupSpecifier>,
+
+        /// Update even if the entity has been edited since the input was fetched
+        #[structopt(long)]
+        force: bool,
+    },
     Edit {
         specifier: Specifier,
 
         #[structopt(long = "--editgroup-id", short, env = "FATCAT_EDITGROUP", hide_env_values = true)]
-        editgroup_id: String,
+        editgroup_id: Option<EditgroupSpecifier>,
 
         #[structopt(long)]
         json: bool,
 
         #[structopt(long = "--editing-command", env = "EDITOR")]
         editing_command: String,
+
+        /// Update even if the entity has been edited since the input was fetched
+        #[structopt(long)]
+        force: bool,
     },
     Delete {
         specifier: Specifier,
 
         #[structopt(long = "--editgroup-id", short, env = "FATCAT_EDITGROUP", hide_env_values = true)]
-        editgroup_id: String,
+        editgroup_id: Option<EditgroupSpecifier>,
+    },
+    BatchUpdate {
+        entity_type: EntityType,
+
+        /// Search query terms; every hit is re-fetched from the API and updated
+        terms: Vec<String>,
+
+        /// Field mutation to apply, like "release_stage=published"; pass multiple times
+        #[structopt(long = "--update", short = "-u", required = true, number_of_values = 1)]
+        mutations: Vec<Mutation>,
+
+        /// Only update hits where field:value matches (a value of "*" matches any value); may be repeated
+        #[structopt(long, number_of_values = 1)]
+        filter: Vec<SearchFilter>,
+
+        /// Don't update hits where field:value matches; may be repeated
+        #[structopt(long, number_of_values = 1)]
+        exclude: Vec<SearchFilter>,
+
+        /// Only update hits published in these years, like "2000-2010", "2000-", or "1999"
+        #[structopt(long = "--year-range")]
+        year_range: Option<YearRange>,
+
+        /// Re-check each hit against the API, and skip (and report) hits which are stale in the
+        /// search index
+        #[structopt(long)]
+        verify: bool,
+
+        /// Maximum number of search hits to process (default: all)
+        #[structopt(long, short = "-n")]
+        limit: Option<u64>,
+
+        /// Number of edits per editgroup; a new editgroup is created when one is full
+        #[structopt(long = "--batch-size", default_value = "50", parse(try_from_str = parse_batch_size))]
+        batch_size: usize,
+
+        /// Description for any newly created editgroups
+        #[structopt(long, short)]
+        description: Option<String>,
+
+        /// Editgroup for the first batch of edits; a new editgroup is created if not provided
+        #[structopt(long = "--editgroup-id", short, env = "FATCAT_EDITGROUP", hide_env_values = true)]
+        editgroup_id: Option<EditgroupSpecifier>,
+
+        /// Update even if the entity has been edited since the input was fetched
+        #[structopt(long)]
+        force: bool,
+    },
+    DeleteMulti {
+        /// Input file (one specifier per line), "-" for stdin.
+        #[structopt(long = "--file", short = "-f", parse(from_os_str))]
+        input_path: Option<PathBuf>,
+
+        /// Number of edits per editgroup; a new editgroup is created when one is full
+        #[structopt(long = "--batch-size", default_value = "50", parse(try_from_str = parse_batch_size))]
+        batch_size: usize,
+
+        /// Description for any newly created editgroups
+        #[structopt(long, short)]
+        description: Option<String>,
+
+        /// Editgroup for the first batch of edits; a new editgroup is created if not provided
+        #[structopt(long = "--editgroup-id", short, env = "FATCAT_EDITGROUP", hide_env_values = true)]
+        editgroup_id: Option<EditgroupSpecifier>,
+    },
+    Diff {
+        /// Entity specifier or revision (eg, "release_rev:<uuid>")
+        old: Specifier,
+
+        /// Entity specifier, revision, or local JSON/TOML file. If not provided, compares against
+        /// the version of the first entity in --editgroup-id.
+        new: Option<String>,
+
+        #[structopt(long = "--editgroup-id", short)]
+        editgroup_id: Option<EditgroupSpecifier>,
+
+        /// Output a JSON Patch (RFC 6902) instead of a human-readable diff. Like the regular diff,
+        /// it only covers entity content: ident, revision, state, and null fields are left out
+        #[structopt(long = "--json-patch")]
+        json_patch: bool,
     },
     Editgroup {
         #[structopt(subcommand)]
         cmd: EditgroupCommand,
     },
-    //Changelog
+    Config {
+        #[structopt(subcommand)]
+        cmd: ConfigCommand,
+    },
+    Auth {
+        #[structopt(subcommand)]
+        cmd: AuthCommand,
+    },
+    Changelog {
+        #[structopt(long, short = "-n", default_value = "20")]
+        limit: i64,
+
+        /// Keep polling the API and print new entries as they are merged
+        #[structopt(long, short = "-f")]
+        follow: bool,
+
+        /// Seconds to wait between polls when following
+        #[structopt(long = "--poll-interval", default_value = "10")]
+        poll_interval: u64,
+
+        #[structopt(long)]
+        json: bool,
+    },
     //Download
-    //History
+    History {
+        specifier: Specifier,
+
+        #[structopt(long, short = "-n", default_value = "100")]
+        limit: i64,
+
+        #[structopt(long)]
+        json: bool,
+    },
     Search {
 
-        entity_type: EntityType,
+        /// What to search for: release, work, container, file, or changelog
+        index: SearchIndex,
 
         terms: Vec<String>,
 
@@ -154,11 +424,50 @@ enum Command {
 
         #[structopt(long = "--search-schema")]
         search_schema: bool,
+
+        /// Only include hits where field:value matches (a value of "*" matches any value); may be repeated
+        #[structopt(long, number_of_values = 1)]
+        filter: Vec<SearchFilter>,
+
+        /// Exclude hits where field:value matches; may be repeated
+        #[structopt(long, number_of_values = 1)]
+        exclude: Vec<SearchFilter>,
+
+        /// Only include hits published in these years, like "2000-2010", "2000-", or "1999"
+        #[structopt(long = "--year-range")]
+        year_range: Option<YearRange>,
+
+        /// Sort by a field, like "year" or "year:desc" (default: relevance)
+        #[structopt(long)]
+        sort: Option<SearchSort>,
+
+        /// Only include hits with a copy preserved in the Internet Archive
+        #[structopt(long = "--in-ia")]
+        in_ia: bool,
+
+        /// Raw Elasticsearch query (JSON object), used instead of query terms; filters still apply
+        #[structopt(long = "--es-query", parse(try_from_str = serde_json::from_str))]
+        es_query: Option<serde_json::Value>,
+
+        /// Only print the total number of hits (for works, an approximate number of works)
+        #[structopt(long)]
+        count: bool,
+
+        /// Count hits by field, like "year" or "type:50" (number of buckets); date fields can
+        /// take an interval, like "release_date:month". May be repeated
+        #[structopt(long, number_of_values = 1)]
+        agg: Vec<SearchAgg>,
+
+        /// Fetch each hit from the API and skip (and report) hits which are stale: the revision
+        /// differs from the search index, or the entity no longer matches the filters
+        #[structopt(long)]
+        verify: bool,
     },
 }
 
 fn main() -> Result<()> {
     let opt = Opt::from_args();
+    set_color_mode(opt.color);
 
     let log_level = match opt.verbose {
         std::i8::MIN..=-1 => "none",
@@ -186,13 +495,7 @@ fn main() -> Result<()> {
                 std::process::exit(0);
             }
         }
-        let mut color_stderr = StandardStream::stderr(
-            if atty::is(atty::Stream::Stderr) {
-                ColorChoice::Auto
-            } else {
-                ColorChoice::Never
-            }
-        );
+        let mut color_stderr = StandardStream::stderr(color_choice(atty::Stream::Stderr));
         color_stderr.set_color(ColorSpec::new().set_fg(Some(Color::Red)).set_bold(true))?;
         eprintln!("Error: {:?}", err);
         color_stderr.set_color(&ColorSpec::new())?;
@@ -202,54 +505,162 @@ fn main() -> Result<()> {
 }
 
 fn run(opt: Opt) -> Result<()> {
-    let client = if opt.api_host.starts_with("https://") {
+    // config commands need to work even if the config file is broken
+    if let Command::Config { ref cmd } = opt.cmd {
+        return run_config(cmd, opt.profile.as_deref());
+    }
+
+    // every single command would create (and then forget about) another editgroup
+    if let Some(EditgroupSpecifier::New(_)) = std::env::var("FATCAT_EDITGROUP").ok().and_then(|v| EditgroupSpecifier::from_str(&v).ok()) {
+        eprintln!("Warning: FATCAT_EDITGROUP=new creates a new editgroup for every command; run 'editgroup use new' once instead");
+    }
+
+    // flags and environment variables take priority over the config file profile
+    let profile = CliConfig::load()?.profile(opt.profile.as_deref())?;
+    let (api_host, api_token) = profile.api_host_and_token(opt.profile.is_some(), opt.api_host, opt.api_token)?;
+    let api_host = api_host.unwrap_or_else(|| "https://api.fatcat.wiki".to_string());
+    let search_host = opt.search_host.or_else(|| profile.search_host.clone())
+        .unwrap_or_else(|| "https://search.fatcat.wiki".to_string());
+    let default_description = profile.editgroup_description.clone();
+    let output = match opt.output {
+        Some(output) => Some(output),
+        None => profile.output_format()?,
+    };
+    // entities and edits are JSON by default; listings and summaries are tables
+    let doc_output = output.unwrap_or(OutputFormat::Json);
+    let table_output = output.unwrap_or(OutputFormat::Table);
+
+    let client = if api_host.starts_with("https://") {
         // Using Simple HTTPS
-        client::Client::try_new_https(&opt.api_host).context("Failed to create HTTPS client")?
-    } else if opt.api_host.starts_with("http://") {
+        client::Client::try_new_https(&api_host).context("Failed to create HTTPS client")?
+    } else if api_host.starts_with("http://") {
         // Using HTTP
-        client::Client::try_new_http(&opt.api_host).context("Failed to create HTTP client")?
+        client::Client::try_new_http(&api_host).context("Failed to create HTTP client")?
     } else {
-        return Err(anyhow!("unsupported API Host prefix: {}", opt.api_host));
+        return Err(anyhow!("unsupported API Host prefix: {}", api_host));
     };
 
-    let mut api_client = FatcatApiClient::new(&client, opt.api_host.clone(), opt.api_token.clone())?;
+    let mut api_client = FatcatApiClient::new(&client, api_host.clone(), api_token.clone())?;
 
     match opt.cmd {
         Command::Get {toml, specifier, expand, hide } => {
+            let output = if toml { OutputFormat::Toml } else { doc_output };
+            let expand = citation_expand(output, expand);
             let result = specifier.get_from_api(&mut api_client, expand, hide)?;
-            if toml {
-                writeln!(&mut std::io::stdout(), "{}", result.to_toml_string()?)?
-            } else {
-                writeln!(&mut std::io::stdout(), "{}", result.to_json_string()?)?
-            }
+            let mut printer = RecordPrinter::new(output, &[]);
+            printer.print_value(result.to_json_value()?)?;
+            printer.finish()?;
         },
-        Command::Create { entity_type, input_path, editgroup_id } => {
-            let json_str = read_entity_file(input_path)?;
+        Command::Create { entity_type, input_path, input_format, editgroup_id } => {
+            let editgroup_id = require_editgroup(editgroup_id, &mut api_client, &api_host, default_description.clone())?;
+            let json_str = read_entity_file(input_path, input_format)?;
             let ee = api_client.create_entity_from_json(entity_type, &json_str, editgroup_id)?;
-            println!("{}", serde_json::to_string(&ee)?);
+            let mut printer = RecordPrinter::new(doc_output, EDIT_COLUMNS);
+            printer.print(&ee)?;
+            printer.finish()?;
         },
-        Command::Update { specifier, input_path, editgroup_id, mutations } => {
-            let (json_str, exact_specifier): (String, Specifier) = match (&input_path, mutations.len()) {
+        Command::CreateMulti { entity_type, input_path, batch_size, auto_batch, description, editgroup_id } => {
+            let description = description.or(default_description);
+            if auto_batch && editgroup_id.is_some() {
+                return Err(anyhow!("--auto-batch creates its own editgroups; it can't be combined with --editgroup-id (or FATCAT_EDITGROUP)"));
+            }
+            let entities = read_entity_stream(input_path, Some(InputFormat::Json))?;
+            if auto_batch {
+                let mut printer = RecordPrinter::new(doc_output, EDITGROUP_COLUMNS);
+                let mut batch: Vec<String> = Vec::with_capacity(batch_size);
+                for json_str in entities {
+                    batch.push(json_str?);
+                    if batch.len() >= batch_size {
+                        let eg = api_client.create_batch_from_json(entity_type, &batch, description.clone())?;
+                        printer.print(&eg)?;
+                        batch.clear();
+                    }
+                }
+                if !batch.is_empty() {
+                    let eg = api_client.create_batch_from_json(entity_type, &batch, description)?;
+                    printer.print(&eg)?;
+                }
+                printer.finish()?;
+            } else {
+                let editgroup_id = match editgroup_or_current(editgroup_id, &mut api_client, &api_host, description)? {
+                    Some(eg_id) => eg_id,
+                    None => return Err(anyhow!("require either --editgroup-id (or a current editgroup) or --auto-batch")),
+                };
+                let mut printer = RecordPrinter::new(doc_output, EDIT_COLUMNS);
+                for json_str in entities {
+                    let ee = api_client.create_entity_from_json(entity_type, &json_str?, editgroup_id.clone())?;
+                    printer.print(&ee)?;
+                }
+                printer.finish()?;
+            }
+        },
+        Command::Update { specifier, input_path, input_format, editgroup_id, base, force, mutations } => {
+            let editgroup_id = require_editgroup(editgroup_id, &mut api_client, &api_host, default_description.clone())?;
+            let (json_str, exact_specifier, expected_revision): (String, Specifier, Option<String>) = match (&input_path, mutations.len(), base) {
+                (Some(_), _, Some(_)) => {
+                    return Err(anyhow!("--base can't be combined with --file; the input file is the full new version of the entity"));
+                },
                 // input path or no mutations: read from path or stdin
-                (Some(_), _) | (None, 0) => {
-                    (read_entity_file(input_path)?, specifier.into_entity_specifier(&mut api_client)?)
+                (Some(_), _, _) | (None, 0, None) => {
+                    let json_str = read_entity_file(input_path, input_format)?;
+                    let expected_revision = entity_revision(&json_str)?;
+                    (json_str, specifier.into_entity_specifier(&mut api_client)?, expected_revision)
+                },
+                // no input path, but a base entity or revision: fetch that from API
+                (None, _, Some(base)) => {
+                    let exact_specifier = specifier.into_entity_specifier(&mut api_client)?;
+                    if base.entity_type() != exact_specifier.entity_type() {
+                        return Err(anyhow!("base entity type doesn't match: {:?} vs {:?}", base, exact_specifier));
+                    }
+                    let mut entity = base.get_from_api(&mut api_client, None, None)?;
+                    if !mutations.is_empty() {
+                        entity.mutate(mutations)?;
+                    }
+                    // restoring an old revision is expected to not match the current revision
+                    (entity.to_json_string()?, exact_specifier, None)
                 },
                 // no input path *and* mutations: fetch from API
-                (None, _) => {
-                    let mut entity = specifier.get_from_api(&mut api_client, None, None)?;
+                (None, _, None) => {
+                    let exact_specifier = specifier.into_entity_specifier(&mut api_client)?;
+                    let mut entity = api_client.get_entity_in_editgroup(&exact_specifier, &editgroup_id)?;
                     entity.mutate(mutations)?;
-                    (entity.to_json_string()?, entity.specifier())
+                    let json_str = entity.to_json_string()?;
+                    let expected_revision = entity_revision(&json_str)?;
+                    (json_str, exact_specifier, expected_revision)
                 },
             };
-            let ee = api_client.update_entity_from_json(exact_specifier, &json_str, editgroup_id)?;
-            println!("{}", serde_json::to_string(&ee)?);
+            let mut counts = UpdateCounts::new(doc_output);
+            counts.record(api_client.update_entity_checked(exact_specifier, &json_str, expected_revision, editgroup_id, force)?)?;
+            counts.finish(false)?;
         },
-        Command::Edit { specifier, editgroup_id, json, editing_command } => {
-            // TODO: fetch editgroup, check if this entity is already being updated in it. If so,
-            // need to fetch that revision, do the edit, parse that synatx is good, then delete the
-            // existing edit and update with the new one.
-            let original_entity = specifier.get_from_api(&mut api_client, None, None)?;
-            let exact_specifier = original_entity.specifier();
+        Command::UpdateMulti { entity_type, input_path, batch_size, description, editgroup_id, force } => {
+            let description = description.or(default_description);
+            let mut batcher = EditgroupBatcher::new(editgroup_or_current(editgroup_id, &mut api_client, &api_host, description.clone())?, batch_size, description);
+            let mut counts = UpdateCounts::new(doc_output);
+            for json_str in read_entity_stream(input_path, Some(InputFormat::Json))? {
+                let json_str = json_str?;
+                let value: serde_json::Value = serde_json::from_str(&json_str)?;
+                let ident = match value["ident"].as_str() {
+                    Some(ident) => ident,
+                    None => return Err(anyhow!("expected full entity with an 'ident' field: {}", json_str)),
+                };
+                let specifier = Specifier::from_ident(entity_type, ident);
+                let expected_revision = value["revision"].as_str().map(|v| v.to_string());
+                if expected_revision.is_none() && !force {
+                    eprintln!("Warning: no 'revision' in input for {}_{}; not checking for conflicting edits", entity_type, ident);
+                }
+                let result = batcher.update_entity_checked(&mut api_client, specifier, &json_str, expected_revision, force)?;
+                counts.record(result)?;
+            }
+            counts.finish(true)?;
+        },
+        Command::Edit { specifier, editgroup_id, json, editing_command, force } => {
+            let editgroup_id = require_editgroup(editgroup_id, &mut api_client, &api_host, default_description.clone())?;
+            // if this entity already has an edit in the editgroup, start from that revision; the
+            // existing edit gets replaced
+            let exact_specifier = specifier.clone().into_entity_specifier(&mut api_client)?;
+            let original_entity = api_client.get_entity_in_editgroup(&exact_specifier, &editgroup_id)?;
+            let expected_revision = entity_revision(&original_entity.to_json_string()?)?;
             let tmp_file = tempfile::Builder::new()
                 .suffix( if json { ".json" } else { ".toml"} )
                 .tempfile()?;
@@ -266,107 +677,659 @@ fn run(opt: Opt) -> Result<()> {
             if !cmd_status.success() {
                 return Err(anyhow!("editor ({}) exited with non-success status code ({}), bailing on edit", editing_command, cmd_status.code().map(|v| v.to_string()).unwrap_or_else(|| "N/A".to_string())));
             };
-            let json_str = read_entity_file(Some(tmp_file.path().to_path_buf()))?;
+            let json_str = read_entity_file(Some(tmp_file.path().to_path_buf()), None)?;
             // for whatever reason api_client's TCP connection is broken after spawning, so try a
             // dummy call, expected to fail, but connection should re-establish after this
             specifier.get_from_api(&mut api_client, None, None).context("re-fetch").ok();
-            let ee = api_client.update_entity_from_json(exact_specifier, &json_str, editgroup_id).context("updating after edit")?;
-            println!("{}", serde_json::to_string(&ee)?);
+            let result = api_client.update_entity_checked(exact_specifier, &json_str, expected_revision, editgroup_id, force).context("updating after edit")?;
+            let mut counts = UpdateCounts::new(doc_output);
+            counts.record(result)?;
+            counts.finish(false)?;
         },
-        Command::Search { entity_type, terms, limit, search_schema, expand, hide } => {
+        Command::Search { index, terms, limit, search_schema, expand, hide, filter, exclude, year_range, sort, in_ia, es_query, count, agg, verify } => {
             let limit: Option<u64> = match limit {
+                // counts and aggregations don't need any hits
+                _ if count || !agg.is_empty() => Some(0),
                 l if l < 0 => None,
                 l => Some(l as u64),
             };
-            let results = fatcat_cli::crude_search(&opt.search_host, entity_type, limit, terms)
-                .with_context(|| format!("searching for {:?}", entity_type))?;
-            eprintln!("Got {} hits in {}ms", results.count, results.took_ms);
+            let query = SearchQuery {
+                terms,
+                filters: filter,
+                excludes: exclude,
+                year_range,
+                in_ia,
+                sort,
+                es_query,
+                aggs: agg,
+            };
+            if verify {
+                for field in query.check_verify(index)? {
+                    eprintln!("Warning: can't re-check {} against the API; only checking revisions and other filters", field);
+                }
+            }
+            let results = fatcat_cli::crude_search(&search_host, index, limit, &query)
+                .with_context(|| format!("searching for {:?}", index))?;
+            if count {
+                println!("{}", results.count);
+                return Ok(());
+            }
+            print_hit_count(&results);
+            if !query.aggs.is_empty() {
+                return print_aggregations(results.aggregations, table_output);
+            }
+            // "es-json" is the same as --search-schema, with JSON output
+            let search_schema = search_schema || doc_output == OutputFormat::EsJson;
+            let output = if doc_output == OutputFormat::EsJson { OutputFormat::Json } else { doc_output };
+            let expand = citation_expand(output, expand);
+            let columns = match index.entity_type() {
+                Some(entity_type) => entity_columns(entity_type),
+                None => CHANGELOG_COLUMNS,
+            };
+            let mut printer = RecordPrinter::new(output, columns);
+            // several releases can share a work; only show each work once
+            let mut seen_works = std::collections::HashSet::new();
+            let mut stale_count = 0;
             for hit in results {
                 let hit = hit?;
-                match (search_schema, entity_type) {
-                    (true, _) => writeln!(&mut std::io::stdout(), "{}", hit.to_string())?,
-                    (false, EntityType::Release) => {
-                        let specifier = Specifier::Release(hit["ident"].as_str().unwrap().to_string());
-                        let entity = specifier.get_from_api(&mut api_client, expand.clone(), hide.clone())?;
-                        writeln!(&mut std::io::stdout(), "{}", entity.to_json_string()?)?
-                    },
-                    (false, _) => unimplemented!("searching other entity types"),
+                let specifier = index.hit_specifier(&hit)?;
+                if let Specifier::Work(ref work_id) = specifier {
+                    if !seen_works.insert(work_id.clone()) {
+                        continue;
+                    }
+                    // the limit is a number of works, not hits (see `crude_search()`)
+                    if limit.iter().any(|l| seen_works.len() as u64 > *l) {
+                        break;
+                    }
+                }
+                if search_schema && !verify {
+                    printer.print_value(hit)?;
+                    continue;
+                }
+                let entity = specifier.get_from_api(&mut api_client, expand.clone(), hide.clone())?.to_json_value()?;
+                if verify {
+                    if let Some(reason) = query.verify_hit(index, &hit, &entity) {
+                        eprintln!("Skipping stale hit {}_{}: {}", index.entity_type().map(|t| t.to_string()).unwrap_or_default(), hit["ident"].as_str().unwrap_or("-"), reason);
+                        stale_count += 1;
+                        continue;
+                    }
+                }
+                printer.print_value(if search_schema { hit } else { entity })?;
+            }
+            printer.finish()?;
+            if verify {
+                eprintln!("Skipped {} stale hits", stale_count);
+            }
+        },
+        Command::Changelog { limit, follow, poll_interval, json } => {
+            let output = if json { OutputFormat::Json } else { table_output };
+            let mut entry_list = api_client.get_changelog(Some(limit))?;
+            // API returns most recent first; print in index order, like `tail`
+            entry_list.reverse();
+            let last_index = entry_list.last().map(|entry| entry.index);
+            print_changelog_entries(entry_list, output, true)?;
+            if follow {
+                // with nothing printed (eg, "-n 0"), start following from the most recent entry,
+                // instead of filling in the entire changelog
+                let mut last_index = match last_index {
+                    Some(index) => index,
+                    None => api_client.get_changelog(Some(1))?.first().map(|entry| entry.index).unwrap_or(0),
+                };
+                // poll size is independent of --limit (which may be zero); any gap is filled below
+                let poll_limit = std::cmp::max(limit, 50);
+                loop {
+                    std::thread::sleep(std::time::Duration::from_secs(poll_interval));
+                    let mut entry_list = match api_client.get_changelog(Some(poll_limit)) {
+                        Ok(l) => l,
+                        Err(err) => {
+                            eprintln!("Warning: polling changelog failed, will retry: {:?}", err);
+                            continue;
+                        },
+                    };
+                    entry_list.retain(|entry| entry.index > last_index);
+                    entry_list.reverse();
+                    // if more entries were merged than fit in a single poll, fill in the gap
+                    if let Some(first_index) = entry_list.first().map(|entry| entry.index) {
+                        let gap_list: Result<Vec<models::ChangelogEntry>> = ((last_index + 1)..first_index)
+                            .map(|index| api_client.get_changelog_entry(index))
+                            .collect();
+                        let mut gap_list = match gap_list {
+                            Ok(l) => l,
+                            Err(err) => {
+                                eprintln!("Warning: fetching changelog entries after {} failed, will retry: {:?}", last_index, err);
+                                continue;
+                            },
+                        };
+                        gap_list.append(&mut entry_list);
+                        entry_list = gap_list;
+                    }
+                    if let Some(entry) = entry_list.last() {
+                        last_index = entry.index;
+                    }
+                    print_changelog_entries(entry_list, output, false)?;
                 }
             }
         },
+        Command::History { specifier, limit, json } => {
+            let output = if json { OutputFormat::Json } else { table_output };
+            let specifier = specifier.into_entity_specifier(&mut api_client)?;
+            match specifier {
+                Specifier::Editor(editor_id) => {
+                    let eg_list = api_client.get_editor_editgroups(editor_id, Some(limit))?;
+                    print_editgroups(eg_list, output)?;
+                },
+                _ => {
+                    let history_list = specifier.get_history(&mut api_client, Some(limit))?;
+                    print_entity_histories(history_list, output)?;
+                },
+            }
+        },
         Command::Delete { specifier, editgroup_id } => {
+            let editgroup_id = require_editgroup(editgroup_id, &mut api_client, &api_host, default_description.clone())?;
             let result = api_client.delete_entity(specifier.clone(), editgroup_id)
                 .with_context(|| format!("delete entity: {:?}", specifier))?;
-            println!("{}", serde_json::to_string(&result)?);
+            let mut printer = RecordPrinter::new(doc_output, EDIT_COLUMNS);
+            printer.print(&result)?;
+            printer.finish()?;
+        },
+        Command::BatchUpdate { entity_type, terms, mutations, filter, exclude, year_range, verify, limit, batch_size, description, editgroup_id, force } => {
+            let description = description.or(default_description);
+            // fail before searching (and editing), not part way through the batch
+            check_mutations(entity_type, &mutations)?;
+            let index = SearchIndex::from_entity_type(entity_type)?;
+            let query = SearchQuery {
+                terms,
+                filters: filter,
+                excludes: exclude,
+                year_range,
+                ..Default::default()
+            };
+            if verify {
+                for field in query.check_verify(index)? {
+                    eprintln!("Warning: can't re-check {} against the API; only checking revisions and other filters", field);
+                }
+            }
+            let results = fatcat_cli::crude_search(&search_host, index, limit, &query)
+                .with_context(|| format!("searching for {:?}", entity_type))?;
+            print_hit_count(&results);
+            let mut batcher = EditgroupBatcher::new(editgroup_or_current(editgroup_id, &mut api_client, &api_host, description.clone())?, batch_size, description);
+            let mut counts = UpdateCounts::new(doc_output);
+            let mut stale_count = 0;
+            for hit in results {
+                let hit = hit?;
+                let specifier = index.hit_specifier(&hit)?;
+                if verify {
+                    // compare against the live entity, not any edit already in the editgroup
+                    let live_entity = specifier.get_from_api(&mut api_client, None, None)?.to_json_value()?;
+                    if let Some(reason) = query.verify_hit(index, &hit, &live_entity) {
+                        eprintln!("Skipping stale hit {:?}: {}", specifier, reason);
+                        stale_count += 1;
+                        continue;
+                    }
+                }
+                let existing_edit = batcher.existing_edit(&mut api_client, &specifier)?;
+                let mut entity = api_client.get_entity_with_edit(&specifier, &existing_edit)?;
+                let original_json = entity.to_json_string()?;
+                entity.mutate(mutations.clone())?;
+                let json_str = entity.to_json_string()?;
+                // cheap check before going through the full (re-fetching) update check
+                if json_str == original_json {
+                    counts.record(UpdateResult::Unchanged(specifier))?;
+                    continue;
+                }
+                let expected_revision = entity_revision(&original_json)?;
+                let result = batcher.update_entity_checked(&mut api_client, specifier, &json_str, expected_revision, force)?;
+                counts.record(result)?;
+            }
+            if verify {
+                eprintln!("Skipped {} stale hits", stale_count);
+            }
+            counts.finish(true)?;
+        },
+        Command::DeleteMulti { input_path, batch_size, description, editgroup_id } => {
+            let description = description.or(default_description);
+            let input_path = match input_path {
+                Some(s) if s.to_string_lossy() == "-" => None,
+                _ => input_path,
+            };
+            let reader: Box<dyn BufRead> = match input_path {
+                None => Box::new(std::io::BufReader::new(std::io::stdin())),
+                Some(path) => Box::new(std::io::BufReader::new(std::fs::File::open(&path).with_context(|| format!("opening specifier file {:?}", path))?)),
+            };
+            let mut batcher = EditgroupBatcher::new(editgroup_or_current(editgroup_id, &mut api_client, &api_host, description.clone())?, batch_size, description);
+            let mut printer = RecordPrinter::new(doc_output, EDIT_COLUMNS);
+            let (mut success_count, mut failure_count) = (0, 0);
+            for (line_num, line) in reader.lines().enumerate() {
+                let line = line?;
+                let line = line.trim();
+                if line.is_empty() {
+                    continue;
+                }
+                // failures are reported per-line, instead of aborting the whole run
+                let result = Specifier::from_str(line).and_then(|specifier| {
+                    if specifier.entity_type().is_none() {
+                        return Err(anyhow!("not an entity specifier"));
+                    }
+                    batcher.delete_entity(&mut api_client, specifier)
+                });
+                match result {
+                    Ok(ee) => {
+                        success_count += 1;
+                        printer.print(&ee)?;
+                    },
+                    Err(err) => {
+                        failure_count += 1;
+                        eprintln!("line {}: failed to delete {}: {:?}", line_num + 1, line, err);
+                    },
+                }
+            }
+            printer.finish()?;
+            if failure_count > 0 {
+                return Err(anyhow!("{} of {} deletions failed", failure_count, success_count + failure_count));
+            }
+        },
+        Command::Diff { old, new, editgroup_id, json_patch } => {
+            let old_json = old.get_from_api(&mut api_client, None, None)?.to_json_string()?;
+            let new_json = match (new, editgroup_id) {
+                (Some(new), None) if std::path::Path::new(&new).exists() => {
+                    read_entity_file(Some(PathBuf::from(new)), None)?
+                },
+                (Some(new), None) => {
+                    let new = Specifier::from_str(&new).context("expected a specifier or a local file")?;
+                    new.get_from_api(&mut api_client, None, None)?.to_json_string()?
+                },
+                (None, Some(editgroup)) => {
+                    let editgroup_id = editgroup.resolve_existing(&mut api_client)?;
+                    let exact_specifier = old.into_entity_specifier(&mut api_client)?;
+                    let existing_edit = api_client.find_existing_edit(&exact_specifier, &editgroup_id)?;
+                    if existing_edit.is_none() {
+                        return Err(anyhow!("no edit of {:?} in editgroup_{}", exact_specifier, editgroup_id));
+                    }
+                    api_client.get_entity_with_edit(&exact_specifier, &existing_edit)?.to_json_string()?
+                },
+                _ => return Err(anyhow!("expected either a second entity (or file) to compare against, or --editgroup-id")),
+            };
+            let changes = diff_values(
+                &entity_content(&serde_json::from_str(&old_json)?),
+                &entity_content(&serde_json::from_str(&new_json)?),
+            );
+            if json_patch {
+                println!("{}", to_json_patch(&changes));
+            } else {
+                let mut color_stdout = StandardStream::stdout(color_choice(atty::Stream::Stdout));
+                print_diff(&changes, &mut color_stdout)?;
+            }
         },
         Command::Editgroup { cmd: EditgroupCommand::List { editor_id, limit, json } } => {
+            let output = if json { OutputFormat::Json } else { table_output };
             let editor_id = match editor_id.or(api_client.editor_id) {
                 Some(eid) => eid,
                 None => return Err(anyhow!("require either working auth token or --editor-id")),
             };
-            let result = api_client.rt.block_on(
-                api_client.api.get_editor_editgroups(editor_id.clone(), Some(limit), None, None)
-            ).context("fetch editgroups")?;
-            match result {
-                fatcat_openapi::GetEditorEditgroupsResponse::Found(eg_list) => {
-                    print_editgroups(eg_list, json)?;
-                },
-                other => return Err(anyhow!("{:?}", other)).with_context(|| format!("failed to fetch editgroups for editor_{}", editor_id)),
-            }
+            let eg_list = api_client.get_editor_editgroups(editor_id, Some(limit))?;
+            print_editgroups(eg_list, output)?;
         },
         Command::Editgroup { cmd: EditgroupCommand::Reviewable { limit, json } } => {
+            let output = if json { OutputFormat::Json } else { table_output };
             let result = api_client.rt.block_on(
                 api_client.api.get_editgroups_reviewable(Some("editors".to_string()), Some(limit), None, None)
             ).context("fetch reviewable editgroups")?;
             match result {
                 fatcat_openapi::GetEditgroupsReviewableResponse::Found(eg_list) => {
-                    print_editgroups(eg_list, json)?;
+                    print_editgroups(eg_list, output)?;
                 },
                 other => return Err(anyhow!("{:?}", other)).context("failed to fetch reviewable editgroups"),
             }
         },
         Command::Editgroup { cmd: EditgroupCommand::Create { description }} => {
-            let mut eg = models::Editgroup::new();
-            eg.description = Some(description);
-            eg.extra = Some({
-                let mut extra = std::collections::HashMap::new();
-                extra.insert("agent".to_string(), serde_json::Value::String("fatcat-cli".to_string()));
-                extra
-            });
-            let result = api_client.rt.block_on(
-                api_client.api.create_editgroup(eg))?;
-            match result {
-                fatcat_openapi::CreateEditgroupResponse::SuccessfullyCreated(eg) =>
-                    println!("{}", serde_json::to_string(&eg)?),
-                other => return Err(anyhow!("{:?}", other)).context("failed to create editgroup"),
+            let eg = api_client.create_editgroup(description.or(default_description))?;
+            let mut printer = RecordPrinter::new(doc_output, &[]);
+            printer.print(&eg)?;
+            printer.finish()?;
+        },
+        Command::Editgroup { cmd: EditgroupCommand::Show { editgroup_id, diff, json } } => {
+            let output = if json { OutputFormat::Json } else { table_output };
+            if diff && output != OutputFormat::Table {
+                return Err(anyhow!("--diff is only supported with table output"));
+            }
+            let editgroup_id = require_existing_editgroup(editgroup_id, &mut api_client, &api_host)?;
+            let eg = api_client.get_editgroup(editgroup_id)?;
+            if output == OutputFormat::Table || output == OutputFormat::Tsv {
+                print_editgroup_edits(&eg, output)?;
+            } else {
+                let mut printer = RecordPrinter::new(output, &[]);
+                printer.print(&eg)?;
+                printer.finish()?;
             }
+            if diff {
+                let mut color_stdout = StandardStream::stdout(color_choice(atty::Stream::Stdout));
+                let edits = eg.edits.unwrap_or_else(models::EditgroupEdits::new);
+                for entity_type in EntityType::ALL.iter() {
+                    for ee in editgroup_edits_of_type(&edits, *entity_type) {
+                        color_stdout.set_color(ColorSpec::new().set_bold(true))?;
+                        writeln!(&mut color_stdout, "\n{}_{} (edit {})", entity_type, ee.ident, ee.edit_id)?;
+                        color_stdout.reset()?;
+                        let changes = edit_diff(&mut api_client, *entity_type, &ee)?;
+                        print_diff(&changes, &mut color_stdout)?;
+                    }
+                }
+            }
+        },
+        Command::Editgroup { cmd: EditgroupCommand::Annotate { editgroup_id, comment, extra } } => {
+            let extra = match extra {
+                Some(extra) => Some(serde_json::from_str(&extra).context("parsing --extra as a JSON object")?),
+                None => None,
+            };
+            let editgroup_id = require_existing_editgroup(editgroup_id, &mut api_client, &api_host)?;
+            let annotation = api_client.create_editgroup_annotation(editgroup_id, comment, extra)?;
+            let mut printer = RecordPrinter::new(doc_output, &[]);
+            printer.print(&annotation)?;
+            printer.finish()?;
+        },
+        Command::Editgroup { cmd: EditgroupCommand::Annotations { editgroup_id, editor_id, limit, json } } => {
+            let output = if json { OutputFormat::Json } else { table_output };
+            // only looks up an existing editgroup (never creates one), and only if it is needed
+            let editgroup_id = match (&editor_id, editgroup_id) {
+                (Some(_), _) => None,
+                (None, Some(spec)) => Some(spec.resolve_existing(&mut api_client)?),
+                (None, None) => CliState::load()?.current_editgroup(&api_host),
+            };
+            let annotation_list = match (editor_id, editgroup_id) {
+                (Some(editor_id), _) => api_client.get_editor_annotations(editor_id, Some(limit))?,
+                (None, Some(editgroup_id)) => {
+                    // the API returns all annotations for an editgroup
+                    let mut annotation_list = api_client.get_editgroup_annotations(editgroup_id)?;
+                    if limit >= 0 {
+                        annotation_list.truncate(limit as usize);
+                    }
+                    annotation_list
+                },
+                (None, None) => match api_client.editor_id.clone() {
+                    Some(editor_id) => api_client.get_editor_annotations(editor_id, Some(limit))?,
+                    None => return Err(anyhow!("require an editgroup, --editor-id, or a working auth token")),
+                },
+            };
+            print_annotations(annotation_list, output)?;
         },
         Command::Editgroup { cmd: EditgroupCommand::Accept { editgroup_id } } => {
+            let editgroup_id = require_existing_editgroup(editgroup_id, &mut api_client, &api_host)?;
             let result = api_client.rt.block_on(
                 api_client.api.accept_editgroup(editgroup_id.clone())
             ).context("accept editgroup")?;
             match result {
-                fatcat_openapi::AcceptEditgroupResponse::MergedSuccessfully(msg) =>
-                    println!("{}", serde_json::to_string(&msg)?),
+                fatcat_openapi::AcceptEditgroupResponse::MergedSuccessfully(msg) => {
+                    let mut printer = RecordPrinter::new(doc_output, &[]);
+                    printer.print(&msg)?;
+                    printer.finish()?;
+                },
                 other => return Err(anyhow!("failed to accept editgroup {}: {:?}", editgroup_id, other)),
             }
+            // accepted editgroups can't be edited further, so stop using it
+            let mut state = CliState::load()?;
+            if state.current_editgroup(&api_host) == Some(editgroup_id) {
+                state.set_current_editgroup(&api_host, None);
+                state.save()?;
+            }
         },
         Command::Editgroup { cmd: EditgroupCommand::Submit{ editgroup_id } } => {
+            let editgroup_id = require_existing_editgroup(editgroup_id, &mut api_client, &api_host)?;
             let eg = api_client.update_editgroup_submit(editgroup_id, true)?;
-            println!("{}", eg.to_json_string()?);
+            let mut printer = RecordPrinter::new(doc_output, &[]);
+            printer.print(&eg)?;
+            printer.finish()?;
         },
         Command::Editgroup { cmd: EditgroupCommand::Unsubmit { editgroup_id } } => {
+            let editgroup_id = require_existing_editgroup(editgroup_id, &mut api_client, &api_host)?;
             let eg = api_client.update_editgroup_submit(editgroup_id, false)?;
-            println!("{}", eg.to_json_string()?);
+            let mut printer = RecordPrinter::new(doc_output, &[]);
+            printer.print(&eg)?;
+            printer.finish()?;
         },
-        Command::Status { json } => {
-            let status = api_client.status()?;
-            if json {
-                println!("{}", serde_json::to_string(&status)?)
+        Command::Editgroup { cmd: EditgroupCommand::Use { editgroup, description } } => {
+            let description = description.or(default_description);
+            let mut state = CliState::load()?;
+            if editgroup == "none" {
+                state.set_current_editgroup(&api_host, None);
+                state.save()?;
+                eprintln!("Cleared current editgroup for {}", api_host);
+                return Ok(());
+            }
+            let eg_id = EditgroupSpecifier::from_str(&editgroup)?.resolve(&mut api_client, description)?;
+            let eg = api_client.get_editgroup(eg_id.clone())?;
+            if eg.changelog_index.is_some() {
+                return Err(anyhow!("editgroup_{} has already been accepted", eg_id));
+            }
+            state.set_current_editgroup(&api_host, Some(eg_id.clone()));
+            state.save()?;
+            eprintln!("Using editgroup_{} for {}", eg_id, api_host);
+            let mut printer = RecordPrinter::new(doc_output, &[]);
+            printer.print(&eg)?;
+            printer.finish()?;
+        },
+        Command::Auth { cmd: AuthCommand::Inspect { json } } => {
+            let output = if json { OutputFormat::Json } else { table_output };
+            let info = match api_token {
+                Some(ref token) => parse_macaroon_token(token)?,
+                None => return Err(anyhow!("no API token configured (--api-token, FATCAT_API_AUTH_TOKEN, or config profile)")),
+            };
+            if output == OutputFormat::Table {
+                info.pretty_print()?;
             } else {
+                let mut printer = RecordPrinter::new(output, &[]);
+                printer.print(&info)?;
+                printer.finish()?;
+            }
+            if info.is_expired() {
+                eprintln!("Warning: API token has expired; create a new one with 'auth create-token'");
+            }
+        },
+        Command::Auth { cmd: AuthCommand::CreateToken { editor_id, duration } } => {
+            let editor_id = match editor_id.or_else(|| api_client.editor_id.clone()) {
+                Some(eid) => eid,
+                None => return Err(anyhow!("require either working auth token or --editor-id")),
+            };
+            let token = api_client.create_auth_token(editor_id.clone(), duration)?;
+            if let Some(expires) = parse_macaroon_token(&token)?.expires {
+                eprintln!("Created token for editor_{}, expires {}", editor_id, expires.to_rfc3339());
+            }
+            println!("{}", token);
+        },
+        Command::Config { .. } => unreachable!("handled above"),
+        Command::Status { json } => {
+            let output = if json { OutputFormat::Json } else { table_output };
+            let mut status = api_client.status()?;
+            status.current_editgroup = CliState::load()?.current_editgroup(&api_host);
+            if output == OutputFormat::Table {
                 status.pretty_print()?;
+            } else {
+                let mut printer = RecordPrinter::new(output, &[]);
+                printer.print(&status)?;
+                printer.finish()?;
+            }
+        },
+    }
+    Ok(())
+}
+
+fn run_config(cmd: &ConfigCommand, profile: Option<&str>) -> Result<()> {
+    let path = CliConfig::path()?;
+    match cmd {
+        ConfigCommand::Show => {
+            let config = CliConfig::load()?;
+            // check that the selected profile exists
+            config.profile(profile)?;
+            if let Some(name) = profile.or_else(|| config.default_profile.as_deref()) {
+                println!("# active profile: {}", name);
             }
+            print!("{}", toml::to_string(&config.redacted())?);
+        },
+        ConfigCommand::Path => {
+            println!("{}", path.display());
+        },
+        ConfigCommand::Edit { editing_command } => {
+            if !path.exists() {
+                if let Some(dir) = path.parent() {
+                    std::fs::create_dir_all(dir).with_context(|| format!("creating config directory {:?}", dir))?;
+                }
+                std::fs::write(&path, CONFIG_TEMPLATE).with_context(|| format!("writing config file {:?}", path))?;
+            }
+            let cmd_status = std::process::Command::new(editing_command)
+                .arg(&path)
+                .status()
+                .with_context(|| format!("running editor ({})", editing_command))?;
+            if !cmd_status.success() {
+                return Err(anyhow!("editor ({}) exited with non-success status code ({})", editing_command, cmd_status.code().map(|v| v.to_string()).unwrap_or_else(|| "N/A".to_string())));
+            };
+            CliConfig::load().context("config file saved, but is not valid")?;
+            eprintln!("Config file OK: {}", path.display());
         },
     }
     Ok(())
 }
+
+/// Aggregations are a table of (field, key, count) rows, or one record per aggregation
+fn print_aggregations(aggregations: Vec<SearchAggResult>, output: OutputFormat) -> Result<()> {
+    if output != OutputFormat::Table && output != OutputFormat::Tsv {
+        let mut printer = RecordPrinter::new(output, &[]);
+        for agg in aggregations.iter() {
+            printer.print(agg)?;
+        }
+        return printer.finish();
+    }
+    let mut printer = RecordPrinter::new(output, &["field", "key", "count"]);
+    for agg in aggregations {
+        for bucket in agg.buckets {
+            printer.print_value(serde_json::json!({"field": agg.field, "key": bucket.key, "count": bucket.count}))?;
+        }
+        if agg.other_count > 0 {
+            printer.print_value(serde_json::json!({"field": agg.field, "key": "(other)", "count": agg.other_count}))?;
+        }
+    }
+    printer.finish()
+}
+
+/// Citations need the container (journal) name, so expand it unless told otherwise
+/// Work searches count works when hits are collapsed, and otherwise count releases
+fn print_hit_count(results: &SearchResults) {
+    match (results.index, results.collapsed) {
+        (_, true) => eprintln!("Got about {} works in {}ms", results.count, results.took_ms),
+        (SearchIndex::Work, false) => eprintln!("Got {} release hits (before grouping by work) in {}ms", results.count, results.took_ms),
+        _ => eprintln!("Got {} hits in {}ms", results.count, results.took_ms),
+    }
+}
+
+fn citation_expand(output: OutputFormat, expand: Option<String>) -> Option<String> {
+    match expand {
+        None if output.is_citation() => Some("container".to_string()),
+        other => other,
+    }
+}
+
+/// Parses a number of edits per editgroup, which has to be at least one
+fn parse_batch_size(s: &str) -> Result<usize> {
+    match s.parse::<usize>().with_context(|| format!("parsing batch size: {}", s))? {
+        0 => Err(anyhow!("batch size must be at least 1")),
+        n => Ok(n),
+    }
+}
+
+/// Parses a duration like "3600", "60m", "12h", "90d", or "2w" into seconds
+fn parse_duration(s: &str) -> Result<i32> {
+    let (num, unit) = match s.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => (&s[..i], c),
+        _ => (s, 's'),
+    };
+    let multiplier = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        'w' => 7 * 24 * 60 * 60,
+        other => return Err(anyhow!("unknown duration unit '{}' (expected s, m, h, d, or w)", other)),
+    };
+    let num: i32 = num.parse().with_context(|| format!("parsing duration: {}", s))?;
+    num.checked_mul(multiplier).filter(|v| *v > 0).ok_or_else(|| anyhow!("invalid duration: {}", s))
+}
+
+/// Running tally of checked entity updates
+struct UpdateCounts {
+    updated: u64,
+    unchanged: u64,
+    conflicted: u64,
+    printer: RecordPrinter,
+}
+
+impl UpdateCounts {
+
+    fn new(output: OutputFormat) -> Self {
+        UpdateCounts {
+            updated: 0,
+            unchanged: 0,
+            conflicted: 0,
+            printer: RecordPrinter::new(output, EDIT_COLUMNS),
+        }
+    }
+
+    /// Prints the outcome of a single update: edits to stdout, skips to stderr
+    fn record(&mut self, result: UpdateResult) -> Result<()> {
+        match result {
+            UpdateResult::Updated(ee) => {
+                self.updated += 1;
+                self.printer.print(&ee)?;
+            },
+            UpdateResult::Unchanged(specifier) => {
+                self.unchanged += 1;
+                eprintln!("Skipping unchanged entity: {:?}", specifier);
+            },
+            UpdateResult::Conflict { specifier, expected_revision, current_revision } => {
+                self.conflicted += 1;
+                eprintln!("Skipping {:?}: updated since revision {} (current revision is {}); use --force to overwrite", specifier, expected_revision, current_revision);
+            },
+        }
+        Ok(())
+    }
+
+    /// Optionally prints a summary, and returns an error if any updates conflicted
+    fn finish(self, summary: bool) -> Result<()> {
+        self.printer.finish()?;
+        if summary {
+            eprintln!("Updated {} entities ({} unchanged, {} conflicted)", self.updated, self.unchanged, self.conflicted);
+        }
+        if self.conflicted > 0 {
+            return Err(anyhow!("{} update(s) skipped due to revision conflict", self.conflicted));
+        }
+        Ok(())
+    }
+}
+
+/// Resolves an editgroup argument, falling back to the current editgroup (see `editgroup use`)
+///
+/// `description` is only used if a new editgroup gets created.
+fn editgroup_or_current(editgroup: Option<EditgroupSpecifier>, api_client: &mut FatcatApiClient, api_host: &str, description: Option<String>) -> Result<Option<String>> {
+    match editgroup {
+        Some(spec) => Ok(Some(spec.resolve(api_client, description)?)),
+        None => Ok(CliState::load()?.current_editgroup(api_host)),
+    }
+}
+
+/// Like `editgroup_or_current()`, but returns an error if there is no editgroup at all
+fn require_editgroup(editgroup: Option<EditgroupSpecifier>, api_client: &mut FatcatApiClient, api_host: &str, description: Option<String>) -> Result<String> {
+    match editgroup_or_current(editgroup, api_client, api_host, description)? {
+        Some(eg_id) => Ok(eg_id),
+        None => Err(anyhow!("no editgroup specified (--editgroup-id or FATCAT_EDITGROUP), and no current editgroup set (see 'editgroup use')")),
+    }
+}
+
+/// Like `require_editgroup()`, for commands which act on an existing editgroup: never creates one
+fn require_existing_editgroup(editgroup: Option<EditgroupSpecifier>, api_client: &mut FatcatApiClient, api_host: &str) -> Result<String> {
+    match editgroup {
+        Some(spec) => spec.resolve_existing(api_client),
+        None => require_editgroup(None, api_client, api_host, None),
+    }
+}
+
+/// Parses out the "revision" field (if any) of an entity in JSON form
+fn entity_revision(json_str: &str) -> Result<Option<String>> {
+    let value: serde_json::Value = serde_json::from_str(json_str).context("parsing entity JSON")?;
+    Ok(value["revision"].as_str().map(|v| v.to_string()))
+}
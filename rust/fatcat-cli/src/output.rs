@@ -0,0 +1,301 @@
+
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+use anyhow::{Result, anyhow};
+use serde_json::Value;
+use tabwriter::TabWriter;
+use termcolor::{Buffer, ColorChoice};
+use fatcat_openapi::models;
+use crate::{ApiModelCite, EntityType};
+use crate::diff::strip_nulls;
+use crate::highlight::{highlight_json, highlight_toml};
+
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum OutputFormat {
+    Json,
+    JsonPretty,
+    Toml,
+    Yaml,
+    Table,
+    Tsv,
+    /// Elasticsearch document schema; only applies to search results
+    EsJson,
+    /// Citation formats; only apply to releases
+    Bibtex,
+    CslJson,
+    Ris,
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" | "jsonl" => Ok(OutputFormat::Json),
+            "json-pretty" => Ok(OutputFormat::JsonPretty),
+            "toml" => Ok(OutputFormat::Toml),
+            "yaml" => Ok(OutputFormat::Yaml),
+            "table" => Ok(OutputFormat::Table),
+            "tsv" => Ok(OutputFormat::Tsv),
+            "es-json" => Ok(OutputFormat::EsJson),
+            "bibtex" => Ok(OutputFormat::Bibtex),
+            "csl-json" => Ok(OutputFormat::CslJson),
+            "ris" => Ok(OutputFormat::Ris),
+            _ => Err(anyhow!("invalid output format (expected json, json-pretty, toml, yaml, table, tsv, es-json, bibtex, csl-json, or ris): {}", s)),
+        }
+    }
+}
+
+impl OutputFormat {
+
+    pub fn is_citation(&self) -> bool {
+        matches!(self, OutputFormat::Bibtex | OutputFormat::CslJson | OutputFormat::Ris)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ColorMode {
+    /// Color only when writing to a terminal, and `NO_COLOR` is not set
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ColorMode::Auto),
+            "always" => Ok(ColorMode::Always),
+            "never" => Ok(ColorMode::Never),
+            _ => Err(anyhow!("invalid color mode (expected auto, always, or never): {}", s)),
+        }
+    }
+}
+
+// set once at startup (from --color), and read everywhere colored output is written
+static COLOR_MODE: AtomicU8 = AtomicU8::new(ColorMode::Auto as u8);
+
+pub fn set_color_mode(mode: ColorMode) {
+    COLOR_MODE.store(mode as u8, Ordering::Relaxed);
+}
+
+/// Whether to color output written to the given stream, based on --color, `NO_COLOR`, and
+/// whether the stream is a terminal.
+pub fn color_choice(stream: atty::Stream) -> ColorChoice {
+    let mode = COLOR_MODE.load(Ordering::Relaxed);
+    if mode == ColorMode::Always as u8 {
+        ColorChoice::Always
+    } else if mode == ColorMode::Never as u8 {
+        ColorChoice::Never
+    } else if std::env::var_os("NO_COLOR").map(|v| !v.is_empty()).unwrap_or(false) || !atty::is(stream) {
+        ColorChoice::Never
+    } else {
+        ColorChoice::Auto
+    }
+}
+
+/// Columns for table/TSV output of entities of the given type
+pub fn entity_columns(entity_type: EntityType) -> &'static [&'static str] {
+    match entity_type {
+        EntityType::Release => &["ident", "release_year", "release_type", "release_stage", "title"],
+        EntityType::Work => &["ident", "revision"],
+        EntityType::Container => &["ident", "issnl", "container_type", "name"],
+        EntityType::Creator => &["ident", "orcid", "display_name"],
+        EntityType::File => &["ident", "sha1", "size", "mimetype"],
+        EntityType::FileSet => &["ident", "revision"],
+        EntityType::WebCapture => &["ident", "timestamp", "original_url"],
+    }
+}
+
+/// Columns for table/TSV output of entity edits (the result of mutations)
+pub const EDIT_COLUMNS: &[&str] = &["edit_id", "ident", "revision", "prev_revision", "editgroup_id"];
+
+/// Columns for table/TSV output of editgroups
+pub const EDITGROUP_COLUMNS: &[&str] = &["editgroup_id", "changelog_index", "created", "submitted", "description"];
+
+/// Columns for table/TSV output of changelog entries
+pub const CHANGELOG_COLUMNS: &[&str] = &["index", "editgroup_id", "timestamp"];
+
+/// Writer for hand-formatted table rows: aligned columns for "table", unaligned for "tsv"
+pub fn table_writer(output: OutputFormat) -> Box<dyn Write> {
+    match output {
+        OutputFormat::Table => Box::new(TabWriter::new(std::io::stdout())),
+        _ => Box::new(std::io::stdout()),
+    }
+}
+
+/// Prints a sequence of API objects (entities, edits, editgroups, etc) in the selected format.
+///
+/// Document formats print one document per object: JSON objects one per line, YAML documents
+/// separated by "---", and TOML documents separated by a blank line. Table and TSV formats print
+/// one row per object with the given (top-level) fields as columns; with no columns, each object is
+/// shown as "field, value" rows instead, which works better for single objects.
+///
+/// Citation formats require release entities. CSL-JSON is printed as a single JSON array, which
+/// is closed by `finish()`.
+///
+/// When stdout is a terminal, JSON is pretty-printed; JSON and TOML are also syntax colored
+/// (subject to `color_choice()`).
+pub struct RecordPrinter {
+    output: OutputFormat,
+    columns: Vec<String>,
+    writer: Box<dyn Write>,
+    count: usize,
+    pretty: bool,
+    color: bool,
+}
+
+impl RecordPrinter {
+
+    pub fn new(output: OutputFormat, columns: &[&str]) -> Self {
+        RecordPrinter {
+            output,
+            columns: columns.iter().map(|c| c.to_string()).collect(),
+            writer: table_writer(output),
+            count: 0,
+            pretty: atty::is(atty::Stream::Stdout),
+            color: color_choice(atty::Stream::Stdout) != ColorChoice::Never,
+        }
+    }
+
+    pub fn print<T: serde::Serialize>(&mut self, record: &T) -> Result<()> {
+        self.print_value(serde_json::to_value(record)?)
+    }
+
+    pub fn print_value(&mut self, value: Value) -> Result<()> {
+        match self.output {
+            OutputFormat::Json => self.write_json(&value, self.pretty)?,
+            OutputFormat::JsonPretty => self.write_json(&value, true)?,
+            OutputFormat::Toml => {
+                if self.count > 0 {
+                    writeln!(self.writer)?;
+                }
+                let toml_str = toml::Value::try_from(strip_nulls(&value))?.to_string();
+                if self.color {
+                    let mut buf = Buffer::ansi();
+                    highlight_toml(&mut buf, &toml_str)?;
+                    self.writer.write_all(buf.as_slice())?;
+                } else {
+                    write!(self.writer, "{}", toml_str)?;
+                }
+            },
+            OutputFormat::Yaml => writeln!(self.writer, "{}", serde_yaml::to_string(&value)?.trim_end())?,
+            OutputFormat::Table | OutputFormat::Tsv if self.columns.is_empty() => {
+                if self.count > 0 {
+                    writeln!(self.writer)?;
+                }
+                if let Value::Object(map) = value {
+                    for (key, val) in map.iter() {
+                        if !val.is_null() {
+                            writeln!(self.writer, "{}\t{}", key, table_cell(val))?;
+                        }
+                    }
+                } else {
+                    writeln!(self.writer, "{}", table_cell(&value))?;
+                }
+            },
+            OutputFormat::Table | OutputFormat::Tsv => {
+                if self.count == 0 {
+                    writeln!(self.writer, "{}", self.columns.join("\t"))?;
+                }
+                let row: Vec<String> = self.columns.iter().map(|col| table_cell(&value[col.as_str()])).collect();
+                writeln!(self.writer, "{}", row.join("\t"))?;
+            },
+            OutputFormat::EsJson => return Err(anyhow!("es-json output is only supported for search results")),
+            OutputFormat::Bibtex | OutputFormat::CslJson | OutputFormat::Ris => {
+                let release: models::ReleaseEntity = serde_json::from_value(value)
+                    .map_err(|_| anyhow!("citation output formats are only supported for releases"))?;
+                match self.output {
+                    OutputFormat::Bibtex => {
+                        if self.count > 0 {
+                            writeln!(self.writer)?;
+                        }
+                        write!(self.writer, "{}", release.to_bibtex())?;
+                    },
+                    OutputFormat::Ris => {
+                        if self.count > 0 {
+                            writeln!(self.writer)?;
+                        }
+                        write!(self.writer, "{}", release.to_ris())?;
+                    },
+                    _ => {
+                        write!(self.writer, "{}", if self.count == 0 { "[\n" } else { ",\n" })?;
+                        write!(self.writer, "{}", serde_json::to_string_pretty(&release.to_csl_json())?)?;
+                    },
+                }
+            },
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    fn write_json(&mut self, value: &Value, pretty: bool) -> Result<()> {
+        if self.color {
+            let mut buf = Buffer::ansi();
+            highlight_json(&mut buf, value, pretty)?;
+            self.writer.write_all(buf.as_slice())?;
+            writeln!(self.writer)?;
+        } else if pretty {
+            writeln!(self.writer, "{}", serde_json::to_string_pretty(value)?)?;
+        } else {
+            writeln!(self.writer, "{}", serde_json::to_string(value)?)?;
+        }
+        Ok(())
+    }
+
+    /// Flushes output; table output is only aligned (and written) at this point
+    pub fn finish(mut self) -> Result<()> {
+        if self.output == OutputFormat::CslJson {
+            writeln!(self.writer, "{}", if self.count == 0 { "[]" } else { "\n]" })?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Single-line, tab-free representation of a value, for table and TSV output
+fn table_cell(value: &Value) -> String {
+    match value {
+        Value::Null => "-".to_string(),
+        Value::String(s) => s.split_whitespace().collect::<Vec<&str>>().join(" "),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_output_format() -> () {
+        assert_eq!(OutputFormat::from_str("json").unwrap(), OutputFormat::Json);
+        assert_eq!(OutputFormat::from_str("json-pretty").unwrap(), OutputFormat::JsonPretty);
+        assert_eq!(OutputFormat::from_str("es-json").unwrap(), OutputFormat::EsJson);
+        assert_eq!(OutputFormat::from_str("tsv").unwrap(), OutputFormat::Tsv);
+        assert_eq!(OutputFormat::from_str("csl-json").unwrap(), OutputFormat::CslJson);
+        assert!(OutputFormat::from_str("ris").unwrap().is_citation());
+        assert!(!OutputFormat::Json.is_citation());
+        assert!(OutputFormat::from_str("xml").is_err());
+        assert_eq!(ColorMode::from_str("never").unwrap(), ColorMode::Never);
+        assert!(ColorMode::from_str("sometimes").is_err());
+    }
+
+    #[test]
+    fn test_table_cell() -> () {
+        assert_eq!(table_cell(&json!(null)), "-");
+        assert_eq!(table_cell(&json!("multi\tline\ntitle ")), "multi line title");
+        assert_eq!(table_cell(&json!(2020)), "2020");
+        assert_eq!(table_cell(&json!(["a", "b"])), "[\"a\",\"b\"]");
+    }
+
+    #[test]
+    fn test_toml_nulls() -> () {
+        // TOML has no null; fields with null values are left out
+        assert!(toml::Value::try_from(strip_nulls(&json!({"a": null, "b": [1, null]}))).is_ok());
+    }
+}
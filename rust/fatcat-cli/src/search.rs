@@ -1,173 +1,1086 @@
 
 use serde_json::json;
+use std::collections::VecDeque;
 use std::time::Duration;
-use anyhow::{Result, anyhow};
-use log::{self,info};
-use crate::EntityType;
+use std::str::FromStr;
+use anyhow::{Result, anyhow, Context};
+use log::{self,info,debug,warn};
+use crate::{EntityType, Specifier};
 
 
+/// Elasticsearch index to search, and the kind of API object each hit refers to
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SearchIndex {
+    Release,
+    /// Searches the release index, but each hit refers to the release's work
+    Work,
+    Container,
+    File,
+    Changelog,
+}
+
+impl FromStr for SearchIndex {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "release" => Ok(SearchIndex::Release),
+            "work" => Ok(SearchIndex::Work),
+            "container" => Ok(SearchIndex::Container),
+            "file" => Ok(SearchIndex::File),
+            "changelog" => Ok(SearchIndex::Changelog),
+            _ => Err(anyhow!("no search index for: {} (expected release, work, container, file, or changelog)", s)),
+        }
+    }
+}
+
+impl SearchIndex {
+
+    /// Index to search for entities of the given type, by entity ident
+    pub fn from_entity_type(entity_type: EntityType) -> Result<Self> {
+        match entity_type {
+            EntityType::Release => Ok(SearchIndex::Release),
+            EntityType::Container => Ok(SearchIndex::Container),
+            EntityType::File => Ok(SearchIndex::File),
+            _ => Err(anyhow!("No search index for entity type: {:?}", entity_type)),
+        }
+    }
+
+    fn index_name(&self) -> &'static str {
+        match self {
+            SearchIndex::Release | SearchIndex::Work => "fatcat_release",
+            SearchIndex::Container => "fatcat_container",
+            SearchIndex::File => "fatcat_file",
+            SearchIndex::Changelog => "fatcat_changelog",
+        }
+    }
+
+    /// Fields matched by query terms which don't name a field explicitly
+    fn default_fields(&self) -> &'static [&'static str] {
+        match self {
+            SearchIndex::Release | SearchIndex::Work => &["title^2", "biblio"],
+            SearchIndex::Container => &["name^2", "original_name", "publisher", "biblio"],
+            SearchIndex::File => &["sha1", "sha256", "md5", "release_ids"],
+            SearchIndex::Changelog => &["description", "agent", "username"],
+        }
+    }
+
+    /// Field used for `--year-range`, if this index has one
+    fn year_field(&self) -> Option<&'static str> {
+        match self {
+            SearchIndex::Release | SearchIndex::Work => Some("year"),
+            _ => None,
+        }
+    }
+
+    /// Date fields, which are aggregated as histograms instead of by term
+    fn date_fields(&self) -> &'static [&'static str] {
+        match self {
+            SearchIndex::Release | SearchIndex::Work => &["release_date"],
+            SearchIndex::Changelog => &["timestamp"],
+            _ => &[],
+        }
+    }
+
+    fn has_in_ia(&self) -> bool {
+        matches!(self, SearchIndex::Release | SearchIndex::Work | SearchIndex::File)
+    }
+
+    /// Path (dotted) of the API entity field which holds the same value as a search index field,
+    /// for re-checking filters against entities fetched from the API
+    fn entity_field(&self, es_field: &str) -> Option<&'static str> {
+        let fields: &[(&str, &str)] = match self {
+            SearchIndex::Release => &[
+                ("ident", "ident"), ("revision", "revision"), ("work_id", "work_id"),
+                ("container_id", "container_id"), ("title", "title"), ("subtitle", "subtitle"),
+                ("original_title", "original_title"),
+                ("type", "release_type"), ("release_type", "release_type"),
+                ("stage", "release_stage"), ("release_stage", "release_stage"),
+                ("year", "release_year"), ("release_year", "release_year"),
+                ("release_date", "release_date"), ("withdrawn_status", "withdrawn_status"),
+                ("volume", "volume"), ("issue", "issue"), ("pages", "pages"), ("number", "number"),
+                ("publisher", "publisher"), ("language", "language"), ("license", "license_slug"),
+                ("doi", "ext_ids.doi"), ("wikidata_qid", "ext_ids.wikidata_qid"),
+                ("isbn13", "ext_ids.isbn13"), ("pmid", "ext_ids.pmid"), ("pmcid", "ext_ids.pmcid"),
+                ("core_id", "ext_ids.core"), ("arxiv_id", "ext_ids.arxiv"),
+                ("jstor_id", "ext_ids.jstor"), ("ark_id", "ext_ids.ark"), ("mag_id", "ext_ids.mag"),
+            ],
+            SearchIndex::Container => &[
+                ("ident", "ident"), ("revision", "revision"), ("name", "name"),
+                ("container_type", "container_type"), ("publisher", "publisher"),
+                ("issnl", "issnl"), ("wikidata_qid", "wikidata_qid"),
+            ],
+            SearchIndex::File => &[
+                ("ident", "ident"), ("revision", "revision"), ("sha1", "sha1"),
+                ("sha256", "sha256"), ("md5", "md5"), ("size_bytes", "size"),
+                ("mimetype", "mimetype"), ("release_ids", "release_ids"),
+            ],
+            SearchIndex::Work | SearchIndex::Changelog => &[],
+        };
+        fields.iter().find(|(f, _)| *f == es_field).map(|(_, path)| *path)
+    }
+
+    /// Entity type of the API objects hits refer to (changelog entries are not entities)
+    pub fn entity_type(&self) -> Option<EntityType> {
+        match self {
+            SearchIndex::Release => Some(EntityType::Release),
+            SearchIndex::Work => Some(EntityType::Work),
+            SearchIndex::Container => Some(EntityType::Container),
+            SearchIndex::File => Some(EntityType::File),
+            SearchIndex::Changelog => None,
+        }
+    }
+
+    /// Specifier for fetching the full API object referred to by a search hit
+    pub fn hit_specifier(&self, hit: &serde_json::Value) -> Result<Specifier> {
+        let field = match self {
+            SearchIndex::Work => "work_id",
+            SearchIndex::Changelog => "index",
+            _ => "ident",
+        };
+        let missing = || anyhow!("search hit has no '{}' field", field);
+        Ok(match self {
+            SearchIndex::Release => Specifier::Release(hit[field].as_str().ok_or_else(missing)?.to_string()),
+            SearchIndex::Work => Specifier::Work(hit[field].as_str().ok_or_else(missing)?.to_string()),
+            SearchIndex::Container => Specifier::Container(hit[field].as_str().ok_or_else(missing)?.to_string()),
+            SearchIndex::File => Specifier::File(hit[field].as_str().ok_or_else(missing)?.to_string()),
+            SearchIndex::Changelog => Specifier::Changelog(hit[field].as_i64().ok_or_else(missing)?),
+        })
+    }
+}
+
+
+/// Search index field and value, like "type:book" (from `--filter` or `--exclude`).
+///
+/// A value of "*" matches any (non-null) value of the field.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SearchFilter {
+    pub field: String,
+    pub value: String,
+}
+
+impl FromStr for SearchFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.find(':') {
+            Some(idx) if idx > 0 && idx + 1 < s.len() => Ok(SearchFilter {
+                field: s[..idx].to_string(),
+                value: s[idx + 1..].to_string(),
+            }),
+            _ => Err(anyhow!("expected a filter like field:value, got: {}", s)),
+        }
+    }
+}
+
+impl SearchFilter {
+
+    fn to_es_query(&self) -> serde_json::Value {
+        match self.value.as_str() {
+            "*" => json!({ "exists": { "field": self.field } }),
+            _ => json!({ "term": { self.field.clone(): self.value } }),
+        }
+    }
+}
+
+/// Inclusive range of years, like "2000-2010", "2000-", "-1950", or "1999"
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct YearRange {
+    pub start: Option<i64>,
+    pub end: Option<i64>,
+}
+
+impl FromStr for YearRange {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_year = |y: &str| -> Result<Option<i64>> {
+            match y.trim() {
+                "" => Ok(None),
+                y => Ok(Some(y.parse().map_err(|_| anyhow!("not a valid year range: {}", s))?)),
+            }
+        };
+        let range = match s.find('-') {
+            Some(idx) => YearRange { start: parse_year(&s[..idx])?, end: parse_year(&s[idx + 1..])? },
+            None => {
+                let year = parse_year(s)?;
+                YearRange { start: year, end: year }
+            },
+        };
+        match range {
+            YearRange { start: None, end: None } => Err(anyhow!("not a valid year range: {}", s)),
+            YearRange { start: Some(start), end: Some(end) } if start > end => Err(anyhow!("year range is backwards: {}", s)),
+            range => Ok(range),
+        }
+    }
+}
+
+/// Sort order for search results, like "year" or "year:desc"
+#[derive(Debug, PartialEq, Clone)]
+pub struct SearchSort {
+    pub field: String,
+    pub descending: bool,
+}
+
+impl FromStr for SearchSort {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (field, descending) = match s.rfind(':') {
+            Some(idx) => match &s[idx + 1..] {
+                "asc" => (&s[..idx], false),
+                "desc" => (&s[..idx], true),
+                other => return Err(anyhow!("sort order must be 'asc' or 'desc', got: {}", other)),
+            },
+            None => (s, false),
+        };
+        if field.is_empty() {
+            return Err(anyhow!("expected a sort field, like 'year' or 'year:desc'"));
+        }
+        Ok(SearchSort { field: field.to_string(), descending })
+    }
+}
+
+/// Aggregation over a search index field, like "year", "type:50" (with number of buckets), or
+/// "release_date:month" (with histogram interval: year, quarter, month, week, or day)
+#[derive(Debug, PartialEq, Clone)]
+pub struct SearchAgg {
+    pub field: String,
+    pub size: Option<u64>,
+    pub interval: Option<String>,
+}
+
+const AGG_INTERVALS: &[&str] = &["year", "quarter", "month", "week", "day"];
+
+impl FromStr for SearchAgg {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (field, param) = match s.find(':') {
+            Some(idx) => (&s[..idx], Some(&s[idx + 1..])),
+            None => (s, None),
+        };
+        if field.is_empty() {
+            return Err(anyhow!("expected an aggregation like field or field:size, got: {}", s));
+        }
+        let mut agg = SearchAgg { field: field.to_string(), size: None, interval: None };
+        match param {
+            None => (),
+            Some(p) if AGG_INTERVALS.contains(&p) => agg.interval = Some(p.to_string()),
+            Some(p) => match p.parse::<u64>() {
+                Ok(size) if size > 0 => agg.size = Some(size),
+                _ => return Err(anyhow!("expected a bucket count or interval ({}) after field, got: {}", AGG_INTERVALS.join(", "), p)),
+            },
+        };
+        Ok(agg)
+    }
+}
+
+impl SearchAgg {
+
+    fn to_es_agg(&self, index: SearchIndex) -> Result<serde_json::Value> {
+        // counts for the top years by number of hits wouldn't be in any useful order
+        if index.year_field() == Some(self.field.as_str()) {
+            if self.size.is_some() || self.interval.is_some() {
+                return Err(anyhow!("'{}' is aggregated as a histogram of all years; it doesn't take a bucket count or interval", self.field));
+            }
+            return Ok(json!({
+                "histogram": {
+                    "field": self.field,
+                    "interval": 1,
+                    "min_doc_count": 1,
+                },
+            }));
+        }
+        let is_date = self.interval.is_some() || index.date_fields().contains(&self.field.as_str());
+        if !is_date {
+            return Ok(json!({ "terms": { "field": self.field, "size": self.size.unwrap_or(20) } }));
+        }
+        if self.size.is_some() {
+            return Err(anyhow!("'{}' is aggregated as a date histogram; give an interval instead of a bucket count", self.field));
+        }
+        let interval = self.interval.as_deref().unwrap_or("year");
+        let format = match interval {
+            "year" => "yyyy",
+            "quarter" | "month" => "yyyy-MM",
+            _ => "yyyy-MM-dd",
+        };
+        Ok(json!({
+            "date_histogram": {
+                "field": self.field,
+                "interval": interval,
+                "format": format,
+                "min_doc_count": 1,
+            },
+        }))
+    }
+
+    fn parse_response(&self, agg: &serde_json::Value) -> Result<SearchAggResult> {
+        let buckets = agg["buckets"].as_array()
+            .ok_or_else(|| anyhow!("no buckets in search response for aggregation: {}", self.field))?;
+        let buckets = buckets.iter().map(|bucket| {
+            let key = match (&bucket["key_as_string"], &bucket["key"]) {
+                (serde_json::Value::String(key), _) | (_, serde_json::Value::String(key)) => key.clone(),
+                // histogram keys are floats, even for integer fields
+                (_, key) => match (key.as_i64(), key.as_f64()) {
+                    (Some(i), _) => i.to_string(),
+                    (None, Some(f)) if f.fract() == 0.0 => (f as i64).to_string(),
+                    _ => key.to_string(),
+                },
+            };
+            AggBucket { key, count: bucket["doc_count"].as_u64().unwrap_or(0) }
+        }).collect();
+        Ok(SearchAggResult {
+            field: self.field.clone(),
+            buckets,
+            other_count: agg["sum_other_doc_count"].as_u64().unwrap_or(0),
+        })
+    }
+}
+
+/// Name of the n-th aggregation in search requests and responses
+fn agg_name(n: usize) -> String {
+    format!("agg{}", n)
+}
+
+/// Name of the aggregation counting distinct works in collapsed work searches
+const WORK_COUNT_AGG: &str = "work_count";
+
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct AggBucket {
+    pub key: String,
+    pub count: u64,
+}
+
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct SearchAggResult {
+    pub field: String,
+    pub buckets: Vec<AggBucket>,
+    /// Hits which didn't fit in any of the returned buckets (terms aggregations only)
+    pub other_count: u64,
+}
+
+/// Looks up a dotted path (like "ext_ids.doi") in a JSON object
+fn lookup_path<'a>(value: &'a serde_json::Value, path: &str) -> &'a serde_json::Value {
+    path.split('.').fold(value, |v, key| &v[key])
+}
+
+/// Whether an entity field value matches a filter value, like Elasticsearch would: lists match
+/// if any element matches, and "*" matches anything except null (or an empty list)
+fn filter_value_matches(value: &serde_json::Value, filter_value: &str) -> bool {
+    match value {
+        serde_json::Value::Null => false,
+        serde_json::Value::Array(list) => list.iter().any(|v| filter_value_matches(v, filter_value)),
+        _ if filter_value == "*" => true,
+        serde_json::Value::String(s) => s == filter_value,
+        other => other.to_string() == filter_value,
+    }
+}
+
+/// Everything about a search except the index and paging: query terms, filters, and sort order.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct SearchQuery {
+    /// Lucene query string terms, joined with AND
+    pub terms: Vec<String>,
+    pub filters: Vec<SearchFilter>,
+    pub excludes: Vec<SearchFilter>,
+    pub year_range: Option<YearRange>,
+    pub in_ia: bool,
+    /// Default is relevance, or index order when scrolling
+    pub sort: Option<SearchSort>,
+    /// Raw Elasticsearch query, used instead of query terms (filters still apply)
+    pub es_query: Option<serde_json::Value>,
+    pub aggs: Vec<SearchAgg>,
+}
+
+impl SearchQuery {
+
+    /// The Elasticsearch "query" object: the query string (or raw query), wrapped with filters
+    fn to_es_query(&self, index: SearchIndex) -> Result<serde_json::Value> {
+        let main_query = match self.es_query {
+            Some(ref es_query) if !self.terms.is_empty() => {
+                return Err(anyhow!("can't combine query terms with a raw Elasticsearch query ({})", es_query));
+            },
+            Some(ref es_query) if es_query.is_object() => es_query.clone(),
+            Some(ref es_query) => return Err(anyhow!("raw Elasticsearch query must be a JSON object: {}", es_query)),
+            None => {
+                let query_string: String = if self.terms.is_empty() {
+                    "*".to_string()
+                } else {
+                    self.terms.join(" ")
+                };
+                info!("Search query string: {}", query_string);
+                let query_string = json!({
+                    "query_string": {
+                        "query": query_string,
+                        "default_operator": "AND",
+                        "analyze_wildcard": true,
+                        "allow_leading_wildcard": false,
+                        "lenient": true,
+                        "fields": index.default_fields(),
+                    },
+                });
+                match index {
+                    // for releases, prefer those which are preserved and have complete metadata
+                    SearchIndex::Release | SearchIndex::Work => json!({
+                        "boosting": {
+                            "positive": {
+                                "bool": {
+                                    "must": query_string,
+                                    "should": {
+                                        "term": { "in_ia": true },
+                                    },
+                                },
+                            },
+                            "negative": {
+                                "bool": {
+                                    "should": [
+                                        {"bool": { "must_not" : { "exists": { "field": "title" }}}},
+                                        {"bool": { "must_not" : { "exists": { "field": "year" }}}},
+                                        {"bool": { "must_not" : { "exists": { "field": "type" }}}},
+                                        {"bool": { "must_not" : { "exists": { "field": "stage" }}}},
+                                    ],
+                                },
+                            },
+                            "negative_boost": 0.5,
+                        },
+                    }),
+                    _ => query_string,
+                }
+            },
+        };
+
+        let mut filters: Vec<serde_json::Value> = self.filters.iter().map(|f| f.to_es_query()).collect();
+        if let Some(range) = self.year_range {
+            let year_field = index.year_field()
+                .ok_or_else(|| anyhow!("--year-range is not supported for {:?} search", index))?;
+            let mut bounds = serde_json::Map::new();
+            if let Some(start) = range.start {
+                bounds.insert("gte".to_string(), json!(start));
+            }
+            if let Some(end) = range.end {
+                bounds.insert("lte".to_string(), json!(end));
+            }
+            filters.push(json!({ "range": { year_field: bounds } }));
+        }
+        if self.in_ia {
+            if !index.has_in_ia() {
+                return Err(anyhow!("--in-ia is not supported for {:?} search", index));
+            }
+            filters.push(json!({ "term": { "in_ia": true } }));
+        }
+        let excludes: Vec<serde_json::Value> = self.excludes.iter().map(|f| f.to_es_query()).collect();
+        if filters.is_empty() && excludes.is_empty() {
+            return Ok(main_query);
+        }
+        Ok(json!({
+            "bool": {
+                "must": main_query,
+                "filter": filters,
+                "must_not": excludes,
+            },
+        }))
+    }
+
+    /// Checks whether search hits can be verified against the API (see `verify_hit()`), returning
+    /// the parts of the query which can't be re-checked against entities.
+    pub fn check_verify(&self, index: SearchIndex) -> Result<Vec<String>> {
+        if let SearchIndex::Work | SearchIndex::Changelog = index {
+            return Err(anyhow!("--verify is not supported for {:?} search", index));
+        }
+        let mut unverifiable: Vec<String> = self.filters.iter().chain(self.excludes.iter())
+            .filter(|f| index.entity_field(&f.field).is_none())
+            .map(|f| f.field.clone())
+            .collect();
+        if self.in_ia {
+            unverifiable.push("--in-ia".to_string());
+        }
+        if self.es_query.is_some() {
+            unverifiable.push("--es-query".to_string());
+        }
+        Ok(unverifiable)
+    }
+
+    /// Checks a search hit against the entity as fetched from the API. The search index lags
+    /// behind the database, so the entity may have been updated (or deleted) since it was indexed.
+    ///
+    /// Returns the reason if the hit is stale: the entity is no longer active, the revision
+    /// differs, or the entity no longer matches the filters. Hits which can't be verified (missing
+    /// a revision) are treated as stale. Query terms are not re-checked.
+    pub fn verify_hit(&self, index: SearchIndex, hit: &serde_json::Value, entity: &serde_json::Value) -> Option<String> {
+        if let Some(state) = entity["state"].as_str() {
+            if state != "active" {
+                return Some(format!("entity is {}", state));
+            }
+        }
+        match (hit["revision"].as_str(), entity["revision"].as_str()) {
+            (Some(hit_rev), Some(rev)) if hit_rev != rev => {
+                return Some(format!("revision changed (search index: {}, API: {})", hit_rev, rev));
+            },
+            (Some(_), Some(_)) => (),
+            // without both revisions there is no telling whether the hit is current
+            (None, _) => return Some("search hit has no revision, can't verify".to_string()),
+            (Some(_), None) => return Some("entity has no revision, can't verify".to_string()),
+        }
+        for filter in self.filters.iter() {
+            if let Some(path) = index.entity_field(&filter.field) {
+                if !filter_value_matches(lookup_path(entity, path), &filter.value) {
+                    return Some(format!("no longer matches filter {}:{}", filter.field, filter.value));
+                }
+            }
+        }
+        for exclude in self.excludes.iter() {
+            if let Some(path) = index.entity_field(&exclude.field) {
+                if filter_value_matches(lookup_path(entity, path), &exclude.value) {
+                    return Some(format!("now matches exclusion {}:{}", exclude.field, exclude.value));
+                }
+            }
+        }
+        if let (Some(range), Some(year_field)) = (self.year_range, index.year_field()) {
+            let year = index.entity_field(year_field).and_then(|path| lookup_path(entity, path).as_i64());
+            let in_range = match year {
+                Some(year) => range.start.iter().all(|s| year >= *s) && range.end.iter().all(|e| year <= *e),
+                None => false,
+            };
+            if !in_range {
+                return Some(format!("year ({}) no longer in range", year.map(|y| y.to_string()).unwrap_or_else(|| "none".to_string())));
+            }
+        }
+        None
+    }
+
+    /// Full search request body
+    fn to_es_body(&self, index: SearchIndex, size: u64, scroll_mode: bool) -> Result<serde_json::Value> {
+        // sort by _doc for (potentially) very large result sets
+        let sort = match self.sort {
+            Some(ref sort) => json!({ sort.field.clone(): if sort.descending { "desc" } else { "asc" } }),
+            None if scroll_mode => json!("_doc"),
+            None => json!("_score"),
+        };
+        let mut body = json!({
+            "query": self.to_es_query(index)?,
+            "size": size,
+            "sort": [ sort ],
+        });
+        if size == 0 {
+            // only the count (and aggregations) are wanted, so make the count exact
+            body["track_total_hits"] = json!(true);
+        }
+        // keyed by position, as the same field can be aggregated more than once
+        let mut aggs = serde_json::Map::new();
+        for (i, agg) in self.aggs.iter().enumerate() {
+            aggs.insert(agg_name(i), agg.to_es_agg(index)?);
+        }
+        if index == SearchIndex::Work && !scroll_mode {
+            // one hit per work; the scroll API doesn't support collapsing. The hit total counts
+            // releases from before collapsing, so count works separately (approximately).
+            body["collapse"] = json!({ "field": "work_id" });
+            aggs.insert(WORK_COUNT_AGG.to_string(), json!({
+                "cardinality": { "field": "work_id", "precision_threshold": 40000 },
+            }));
+        }
+        if !aggs.is_empty() {
+            body["aggs"] = serde_json::Value::Object(aggs);
+        }
+        Ok(body)
+    }
+}
+
+
+/// How long Elasticsearch should keep a scroll context open between batches
+const SCROLL_KEEPALIVE: &str = "5m";
+
+/// Number of times a failed search request is retried, with exponential backoff
+const MAX_RETRIES: u32 = 4;
+
+/// Iterator over search hits (the "_source" documents).
+///
+/// Result sets larger than a single batch are paged through with the scroll API. The scroll
+/// context is deleted when the iterator is dropped. If the results end before the expected number
+/// of hits (the total, or `limit` if smaller), the iterator returns an error instead of stopping
+/// silently.
 pub struct SearchResults {
-    pub entity_type: EntityType,
+    pub index: SearchIndex,
     pub limit: Option<u64>,
+    /// Total number of hits; if `collapsed`, an approximate number of works
     pub count: u64,
+    /// Work hits were collapsed to one per work
+    pub collapsed: bool,
     pub took_ms: u64,
+    /// One per `SearchQuery::aggs`, in the same order
+    pub aggregations: Vec<SearchAggResult>,
     offset: u64,
-    batch: Vec<serde_json::Value>,
+    batch: VecDeque<serde_json::Value>,
     scroll_id: Option<String>,
     scroll_url: String,
     http_client: reqwest::Client,
+    done: bool,
+}
+
+impl SearchResults {
+
+    fn fetch_scroll_batch(&mut self, scroll_id: &str) -> Result<()> {
+        let request_body = json!({
+            "scroll": SCROLL_KEEPALIVE,
+            "scroll_id": scroll_id,
+        }).to_string();
+        // each scroll request advances the scroll context, so a request which failed part-way (eg,
+        // timed out) may have used up a batch; retrying would silently skip those hits
+        let body = send_with_retries(false, || {
+            self.http_client.post(&self.scroll_url)
+                .header("Content-Type", "application/json")
+                .body(request_body.clone())
+        }).with_context(|| format!("fetching search results after {} hits", self.offset))?;
+        // the scroll id can change between batches
+        if let Some(scroll_id) = body["_scroll_id"].as_str() {
+            self.scroll_id = Some(scroll_id.to_string());
+        }
+        self.batch = response_hits(&body)?;
+        Ok(())
+    }
 }
 
 impl Iterator for SearchResults {
     type Item = Result<serde_json::Value>;
 
     fn next(&mut self) -> Option<Result<serde_json::Value>> {
+        if self.done {
+            return None;
+        }
         // if we already hit limit, bail early
         if let Some(l) = self.limit {
             if self.offset >= l {
+                self.done = true;
                 return None
             }
         }
         // if current batch is empty, and we are scrolling, refill the current batch
-        if self.batch.is_empty() && self.scroll_id.is_some() {
-            let response = self.http_client.get(&self.scroll_url)
-                .header("Content-Type", "application/json")
-                .body(json!({
-                    "scroll": "2m",
-                    "scroll_id": self.scroll_id.clone().unwrap(),
-                }).to_string())
-                .send();
-            let mut response = match response {
-                Err(e) => return Some(Err(e.into())),
-                Ok(v) => v,
-            };
-            if !response.status().is_success() {
-                return Some(Err(anyhow!("search error, status={}", response.status())));
-            };
-            let body: serde_json::Value = match response.json() {
-                Err(e) => return Some(Err(e.into())),
-                Ok(v) => v,
-            };
-            self.scroll_id = Some(body["_scroll_id"].as_str().unwrap().to_string());
-            self.batch = body["hits"]["hits"].as_array().unwrap().to_vec();
+        if self.batch.is_empty() {
+            if let Some(scroll_id) = self.scroll_id.clone() {
+                if let Err(e) = self.fetch_scroll_batch(&scroll_id) {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
         }
 
         // return next hit from the most recent batch
-        if !self.batch.is_empty() {
+        if let Some(hit) = self.batch.pop_front() {
             self.offset += 1;
-            let val = self.batch.pop().unwrap();
-            let source = val["_source"].clone();
-            return Some(Ok(source));
+            return Some(Ok(hit["_source"].clone()));
         }
 
-        // if batch is empty and couldn't be refilled, terminate
-        // TODO: should we raise error if ended early?
+        // batch is empty and couldn't be refilled; check that we got everything. The work count
+        // for collapsed results is only approximate, and they are never scrolled anyways.
+        self.done = true;
+        if self.collapsed {
+            return None;
+        }
+        let expected = match self.limit {
+            Some(l) => std::cmp::min(l, self.count),
+            None => self.count,
+        };
+        if self.offset < expected {
+            return Some(Err(anyhow!("search results ended early: got {} of {} hits (scroll context may have expired)", self.offset, expected)));
+        }
         None
     }
 }
 
-pub fn crude_search(api_host: &str, entity_type: EntityType, limit: Option<u64>, terms: Vec<String>) -> Result<SearchResults> {
+impl Drop for SearchResults {
+    /// Scroll contexts hold resources on the search cluster until they expire, so clear them
+    /// as soon as we are done (or have given up).
+    fn drop(&mut self) {
+        let scroll_id = match self.scroll_id.take() {
+            Some(scroll_id) => scroll_id,
+            None => return,
+        };
+        let result = self.http_client.delete(&self.scroll_url)
+            .header("Content-Type", "application/json")
+            .body(json!({ "scroll_id": [ scroll_id ] }).to_string())
+            .send();
+        match result {
+            Ok(ref response) if response.status().is_success() => debug!("cleared search scroll context"),
+            // already expired or fully consumed
+            Ok(ref response) if response.status().as_u16() == 404 => debug!("search scroll context already gone"),
+            Ok(response) => warn!("failed to clear search scroll context, status={}", response.status()),
+            Err(e) => warn!("failed to clear search scroll context: {}", e),
+        }
+    }
+}
+
+/// Sends a search request and parses the JSON response, retrying with exponential backoff on
+/// overloaded (429) responses. If `idempotent`, also retries on connection errors and timeouts,
+/// and on failed (5xx) responses.
+///
+/// `build_request` is called once per attempt.
+fn send_with_retries<F: Fn() -> reqwest::RequestBuilder>(idempotent: bool, build_request: F) -> Result<serde_json::Value> {
+    let mut delay = Duration::from_secs(1);
+    let mut attempt = 0;
+    loop {
+        let err = match build_request().send() {
+            Ok(mut response) if response.status().is_success() => return Ok(response.json()?),
+            // the request was rejected without being processed
+            Ok(response) if response.status().as_u16() == 429 => {
+                anyhow!("search error, status={}", response.status())
+            },
+            Ok(response) if response.status().is_server_error() && idempotent => {
+                anyhow!("search error, status={}", response.status())
+            },
+            Ok(mut response) => {
+                // client errors (like a bad query) won't succeed on retry; include the reason
+                let body = response.text().unwrap_or_default();
+                return Err(anyhow!("search error, status={}: {}", response.status(), body));
+            },
+            Err(e) if idempotent => e.into(),
+            Err(e) => return Err(e.into()),
+        };
+        attempt += 1;
+        if attempt > MAX_RETRIES {
+            return Err(err.context(format!("search request failed after {} retries", MAX_RETRIES)));
+        }
+        warn!("search request failed, retrying in {}s: {}", delay.as_secs(), err);
+        std::thread::sleep(delay);
+        delay *= 2;
+    }
+}
+
+fn response_hits(body: &serde_json::Value) -> Result<VecDeque<serde_json::Value>> {
+    match body["hits"]["hits"].as_array() {
+        Some(hits) => Ok(hits.iter().cloned().collect()),
+        None => Err(anyhow!("no hits in search response")),
+    }
+}
+
+/// Runs a search, returning an iterator over the hits.
+///
+/// `limit` is the number of hits to return. For work searches it is the number of works: hits
+/// are collapsed to one per work if possible, but when scrolling (large or no limit) they are not,
+/// and the iterator is not limited, so callers need to de-duplicate works and apply the limit.
+pub fn crude_search(api_host: &str, index: SearchIndex, limit: Option<u64>, query: &SearchQuery) -> Result<SearchResults> {
 
-    let index = match entity_type {
-        EntityType::Release => "fatcat_release",
-        EntityType::File => "fatcat_file",
-        EntityType::Container => "fatcat_container",
-        _ => return Err(anyhow!("No search index for entity type: {:?}", entity_type)),
-    };
     let http_client = reqwest::Client::builder()
-        .timeout(Duration::from_secs(10))
+        .timeout(Duration::from_secs(30))
         .danger_accept_invalid_certs(true)
         .build()
         .expect("ERROR :: Could not build reqwest client");
 
-    let query: String = if terms.is_empty() {
-        "*".to_string()
-    } else {
-        terms.join(" ")
-    };
-    info!("Search query string: {}", query);
-    let request_url = format!("{}/{}/_search", api_host, index);
+    let request_url = format!("{}/{}/_search", api_host, index.index_name());
     let scroll_url = format!("{}/_search/scroll", api_host);
 
-    // sort by _doc for (potentially) very large result sets
-    let (scroll_mode, sort_mode, size) = match limit {
-        None => (true, "_doc", 100),
-        Some(l) if l > 100 => (true, "_doc", 100),
-        Some(l) => (false, "_score", l),
-
+    let (scroll_mode, size) = match limit {
+        None => (true, 100),
+        Some(l) if l > 100 => (true, 100),
+        Some(l) => (false, l),
     };
 
-    let query_body = json!({
-            "query": {
-                "boosting": {
-                    "positive": {
-                        "bool": {
-                            "must": {
-                                "query_string": {
-                                    "query": query,
-                                    "default_operator": "AND",
-                                    "analyze_wildcard": true,
-                                    "allow_leading_wildcard": false,
-                                    "lenient": true,
-                                    "fields": [
-                                        "title^2",
-                                        "biblio",
-                                    ],
-                                },
-                            },
-                            "should": {
-                                "term": { "in_ia": true },
-                            },
-                        },
-                    },
-                    "negative": {
-                        "bool": {
-                            "should": [
-                                {"bool": { "must_not" : { "exists": { "field": "title" }}}},
-                                {"bool": { "must_not" : { "exists": { "field": "year" }}}},
-                                {"bool": { "must_not" : { "exists": { "field": "type" }}}},
-                                {"bool": { "must_not" : { "exists": { "field": "stage" }}}},
-                            ],
-                        },
-                    },
-                    "negative_boost": 0.5,
-                },
-            },
-            "size": size,
-            "sort": [ sort_mode ],
-        }).to_string();
-
-    let mut request = http_client.get(&request_url)
-        .header("Content-Type", "application/json")
-        .body(query_body);
-
-    if scroll_mode {
-        request = request.query(&[("scroll", "2m")]);
-    }
-
-    let mut response = request.send()?;
+    let query_body = query.to_es_body(index, size, scroll_mode)?.to_string();
+    debug!("Search request body: {}", query_body);
 
-    if !response.status().is_success() {
-        return Err(anyhow!("search error, status={}", response.status()));
-    }
-    //println!("{:?}", response);
-    let body: serde_json::Value = response.json()?;
+    let body = send_with_retries(true, || {
+        let request = http_client.get(&request_url)
+            .header("Content-Type", "application/json")
+            .body(query_body.clone());
+        if scroll_mode {
+            request.query(&[("scroll", SCROLL_KEEPALIVE)])
+        } else {
+            request
+        }
+    })?;
 
     let scroll_id = if scroll_mode {
+        match body["_scroll_id"].as_str() {
+            Some(scroll_id) => Some(scroll_id.to_string()),
+            None => return Err(anyhow!("no scroll id in search response")),
+        }
+    } else {
         None
+    };
+
+    let aggregations = query.aggs.iter().enumerate()
+        .map(|(i, agg)| agg.parse_response(&body["aggregations"][agg_name(i).as_str()]))
+        .collect::<Result<Vec<SearchAggResult>>>()?;
+
+    let collapsed = index == SearchIndex::Work && !scroll_mode;
+    let count = if collapsed {
+        body["aggregations"][WORK_COUNT_AGG]["value"].as_u64()
+            .ok_or_else(|| anyhow!("no work count in search response"))?
     } else {
-        Some(body["_scroll_id"].as_str().unwrap().to_string())
+        hits_total(&body)?
     };
 
     Ok(SearchResults {
-        entity_type,
-        limit,
-        count: body["hits"]["total"].as_u64().unwrap(),
-        took_ms: body["took"].as_u64().unwrap(),
+        index,
+        limit: if index == SearchIndex::Work && scroll_mode { None } else { limit },
+        count,
+        collapsed,
+        took_ms: body["took"].as_u64().unwrap_or(0),
+        aggregations,
         offset: 0,
-        batch: body["hits"]["hits"].as_array().unwrap().to_vec(),
+        batch: response_hits(&body)?,
         scroll_id,
         scroll_url,
         http_client,
+        done: false,
     })
 }
+
+/// Elasticsearch 7 returns the total as an object (with "value"), and earlier versions as a number
+fn hits_total(body: &serde_json::Value) -> Result<u64> {
+    let total = &body["hits"]["total"];
+    total.as_u64()
+        .or_else(|| total["value"].as_u64())
+        .ok_or_else(|| anyhow!("no hit count in search response"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_filter() -> () {
+        let f = SearchFilter::from_str("container_id:aaaaaaaaaaaaaeiraaaaaaaaai").unwrap();
+        assert_eq!(f.field, "container_id");
+        assert_eq!(f.to_es_query(), json!({"term": {"container_id": "aaaaaaaaaaaaaeiraaaaaaaaai"}}));
+        assert_eq!(SearchFilter::from_str("doi:10.123/a:b").unwrap().value, "10.123/a:b");
+        assert_eq!(SearchFilter::from_str("doi:*").unwrap().to_es_query(), json!({"exists": {"field": "doi"}}));
+        assert!(SearchFilter::from_str("blah").is_err());
+        assert!(SearchFilter::from_str(":blah").is_err());
+        assert!(SearchFilter::from_str("blah:").is_err());
+    }
+
+    #[test]
+    fn test_year_range() -> () {
+        assert_eq!(YearRange::from_str("2000-2010").unwrap(), YearRange { start: Some(2000), end: Some(2010) });
+        assert_eq!(YearRange::from_str("2000-").unwrap(), YearRange { start: Some(2000), end: None });
+        assert_eq!(YearRange::from_str("-1950").unwrap(), YearRange { start: None, end: Some(1950) });
+        assert_eq!(YearRange::from_str("1999").unwrap(), YearRange { start: Some(1999), end: Some(1999) });
+        assert!(YearRange::from_str("-").is_err());
+        assert!(YearRange::from_str("2010-2000").is_err());
+        assert!(YearRange::from_str("recent").is_err());
+    }
+
+    #[test]
+    fn test_search_sort() -> () {
+        assert_eq!(SearchSort::from_str("year").unwrap(), SearchSort { field: "year".to_string(), descending: false });
+        assert_eq!(SearchSort::from_str("year:desc").unwrap(), SearchSort { field: "year".to_string(), descending: true });
+        assert!(SearchSort::from_str("year:up").is_err());
+        assert!(SearchSort::from_str(":desc").is_err());
+    }
+
+    #[test]
+    fn test_search_query_body() -> () {
+        let query = SearchQuery {
+            terms: vec!["coffee".to_string()],
+            filters: vec![SearchFilter::from_str("type:article-journal").unwrap()],
+            excludes: vec![SearchFilter::from_str("doi:*").unwrap()],
+            year_range: Some(YearRange::from_str("2000-").unwrap()),
+            in_ia: true,
+            sort: Some(SearchSort::from_str("year:desc").unwrap()),
+            ..Default::default()
+        };
+        let body = query.to_es_body(SearchIndex::Release, 20, false).unwrap();
+        assert_eq!(body["sort"], json!([{"year": "desc"}]));
+        assert_eq!(body["query"]["bool"]["filter"], json!([
+            {"term": {"type": "article-journal"}},
+            {"range": {"year": {"gte": 2000}}},
+            {"term": {"in_ia": true}},
+        ]));
+        assert_eq!(body["query"]["bool"]["must_not"], json!([{"exists": {"field": "doi"}}]));
+        assert_eq!(body["query"]["bool"]["must"]["boosting"]["positive"]["bool"]["must"]["query_string"]["query"], "coffee");
+
+        // no filters: just the query string
+        let body = SearchQuery::default().to_es_body(SearchIndex::Release, 100, true).unwrap();
+        assert_eq!(body["sort"], json!(["_doc"]));
+        assert!(body["query"]["boosting"].is_object());
+
+        let raw = SearchQuery { es_query: Some(json!({"match_all": {}})), ..Default::default() };
+        assert_eq!(raw.to_es_body(SearchIndex::Release, 20, false).unwrap()["query"], json!({"match_all": {}}));
+        let both = SearchQuery { terms: vec!["coffee".to_string()], ..raw.clone() };
+        assert!(both.to_es_body(SearchIndex::Release, 20, false).is_err());
+        let not_object = SearchQuery { es_query: Some(json!("coffee")), ..Default::default() };
+        assert!(not_object.to_es_body(SearchIndex::Release, 20, false).is_err());
+
+        // other indexes have their own default fields, and no boosting
+        let body = SearchQuery { terms: vec!["coffee".to_string()], ..Default::default() }
+            .to_es_body(SearchIndex::Container, 20, false).unwrap();
+        assert_eq!(body["query"]["query_string"]["fields"][0], "name^2");
+        let year_query = SearchQuery { year_range: Some(YearRange::from_str("2000").unwrap()), ..Default::default() };
+        assert!(year_query.to_es_body(SearchIndex::Work, 20, false).is_ok());
+        assert!(year_query.to_es_body(SearchIndex::Changelog, 20, false).is_err());
+        let ia_query = SearchQuery { in_ia: true, ..Default::default() };
+        assert!(ia_query.to_es_body(SearchIndex::File, 20, false).is_ok());
+        assert!(ia_query.to_es_body(SearchIndex::Container, 20, false).is_err());
+
+        // work hits are collapsed, except when scrolling
+        let query = SearchQuery::default();
+        assert_eq!(query.to_es_body(SearchIndex::Work, 20, false).unwrap()["collapse"], json!({"field": "work_id"}));
+        assert_eq!(query.to_es_body(SearchIndex::Work, 20, false).unwrap()["aggs"][WORK_COUNT_AGG]["cardinality"]["field"], "work_id");
+        assert!(query.to_es_body(SearchIndex::Work, 100, true).unwrap().get("aggs").is_none());
+        assert!(query.to_es_body(SearchIndex::Work, 100, true).unwrap().get("collapse").is_none());
+        assert!(query.to_es_body(SearchIndex::Release, 20, false).unwrap().get("collapse").is_none());
+    }
+
+    #[test]
+    fn test_search_index() -> () {
+        assert_eq!(SearchIndex::from_str("container").unwrap(), SearchIndex::Container);
+        assert!(SearchIndex::from_str("creator").is_err());
+        assert_eq!(SearchIndex::Changelog.entity_type(), None);
+        assert_eq!(SearchIndex::from_entity_type(EntityType::File).unwrap(), SearchIndex::File);
+        assert!(SearchIndex::from_entity_type(EntityType::Creator).is_err());
+
+        let hit = json!({"ident": "aaaaaaaaaaaaarceaaaaaaaaai", "work_id": "aaaaaaaaaaaaavkvaaaaaaaaai"});
+        assert_eq!(SearchIndex::Release.hit_specifier(&hit).unwrap(), Specifier::Release("aaaaaaaaaaaaarceaaaaaaaaai".to_string()));
+        assert_eq!(SearchIndex::Work.hit_specifier(&hit).unwrap(), Specifier::Work("aaaaaaaaaaaaavkvaaaaaaaaai".to_string()));
+        assert_eq!(SearchIndex::Changelog.hit_specifier(&json!({"index": 1234})).unwrap(), Specifier::Changelog(1234));
+        assert!(SearchIndex::Changelog.hit_specifier(&hit).is_err());
+    }
+
+    #[test]
+    fn test_search_agg() -> () {
+        assert_eq!(SearchAgg::from_str("type").unwrap(), SearchAgg { field: "type".to_string(), size: None, interval: None });
+        assert_eq!(SearchAgg::from_str("type:50").unwrap().size, Some(50));
+        assert_eq!(SearchAgg::from_str("release_date:month").unwrap().interval, Some("month".to_string()));
+        assert!(SearchAgg::from_str("type:0").is_err());
+        assert!(SearchAgg::from_str("type:lots").is_err());
+        assert!(SearchAgg::from_str(":50").is_err());
+
+        let query = SearchQuery {
+            aggs: vec![
+                SearchAgg::from_str("type").unwrap(),
+                SearchAgg::from_str("release_date").unwrap(),
+                SearchAgg::from_str("type:5").unwrap(),
+                SearchAgg::from_str("year").unwrap(),
+            ],
+            ..Default::default()
+        };
+        let body = query.to_es_body(SearchIndex::Release, 0, false).unwrap();
+        assert_eq!(body["track_total_hits"], true);
+        assert_eq!(body["aggs"]["agg0"], json!({"terms": {"field": "type", "size": 20}}));
+        assert_eq!(body["aggs"]["agg1"]["date_histogram"]["interval"], "year");
+        assert_eq!(body["aggs"]["agg2"], json!({"terms": {"field": "type", "size": 5}}));
+        assert_eq!(body["aggs"]["agg3"]["histogram"]["interval"], 1);
+        let bad = SearchQuery { aggs: vec![SearchAgg::from_str("timestamp:10").unwrap()], ..Default::default() };
+        assert!(bad.to_es_body(SearchIndex::Changelog, 0, false).is_err());
+        let bad = SearchQuery { aggs: vec![SearchAgg::from_str("year:10").unwrap()], ..Default::default() };
+        assert!(bad.to_es_body(SearchIndex::Release, 0, false).is_err());
+
+        let resp = json!({
+            "doc_count_error_upper_bound": 0,
+            "sum_other_doc_count": 12,
+            "buckets": [{"key": 2019, "doc_count": 30}, {"key": "2018", "key_as_string": "2018", "doc_count": 4}],
+        });
+        let result = query.aggs[0].parse_response(&resp).unwrap();
+        assert_eq!(result.buckets, vec![
+            AggBucket { key: "2019".to_string(), count: 30 },
+            AggBucket { key: "2018".to_string(), count: 4 },
+        ]);
+        assert_eq!(result.other_count, 12);
+        assert!(query.aggs[0].parse_response(&json!({})).is_err());
+
+        let resp = json!({"buckets": [{"key": 2018.0, "doc_count": 4}, {"key": 2019.0, "doc_count": 30}]});
+        let result = query.aggs[3].parse_response(&resp).unwrap();
+        assert_eq!(result.buckets[0], AggBucket { key: "2018".to_string(), count: 4 });
+    }
+
+    #[test]
+    fn test_hits_total() -> () {
+        assert_eq!(hits_total(&json!({"hits": {"total": 123}})).unwrap(), 123);
+        assert_eq!(hits_total(&json!({"hits": {"total": {"value": 123, "relation": "eq"}}})).unwrap(), 123);
+        assert!(hits_total(&json!({"hits": {}})).is_err());
+    }
+
+    fn example_results(count: u64, limit: Option<u64>, hits: Vec<serde_json::Value>) -> SearchResults {
+        SearchResults {
+            index: SearchIndex::Release,
+            limit,
+            count,
+            collapsed: false,
+            took_ms: 0,
+            aggregations: vec![],
+            offset: 0,
+            batch: response_hits(&json!({"hits": {"hits": hits}})).unwrap(),
+            scroll_id: None,
+            scroll_url: "http://localhost:9200/_search/scroll".to_string(),
+            http_client: reqwest::Client::new(),
+            done: false,
+        }
+    }
+
+    #[test]
+    fn test_search_results() -> () {
+        let hits = vec![json!({"_source": {"ident": "a"}}), json!({"_source": {"ident": "b"}}), json!({"_source": {"ident": "c"}})];
+
+        // hits come back in order
+        let results: Vec<serde_json::Value> = example_results(3, None, hits.clone()).map(|hit| hit.unwrap()).collect();
+        assert_eq!(results, vec![json!({"ident": "a"}), json!({"ident": "b"}), json!({"ident": "c"})]);
+
+        assert_eq!(example_results(3, Some(2), hits.clone()).count(), 2);
+
+        // fewer hits than expected is an error, which is only returned once
+        let mut results = example_results(5, None, hits.clone());
+        assert!(results.by_ref().take(3).all(|hit| hit.is_ok()));
+        assert!(results.next().unwrap().is_err());
+        assert!(results.next().is_none());
+
+        // collapsed work results can be fewer than the (approximate) count and the limit
+        let mut results = example_results(5, Some(20), hits.clone());
+        results.index = SearchIndex::Work;
+        results.collapsed = true;
+        assert_eq!(results.map(|hit| hit.unwrap()).count(), 3);
+
+        assert!(response_hits(&json!({"hits": {}})).is_err());
+    }
+
+    #[test]
+    fn test_verify_hit() -> () {
+        let query = SearchQuery {
+            filters: vec![SearchFilter::from_str("type:article-journal").unwrap(), SearchFilter::from_str("doi:*").unwrap()],
+            excludes: vec![SearchFilter::from_str("stage:draft").unwrap()],
+            year_range: Some(YearRange::from_str("2000-2010").unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(query.check_verify(SearchIndex::Release).unwrap(), Vec::<String>::new());
+        assert!(query.check_verify(SearchIndex::Work).is_err());
+        let other = SearchQuery { filters: vec![SearchFilter::from_str("in_kbart:true").unwrap()], in_ia: true, ..Default::default() };
+        assert_eq!(other.check_verify(SearchIndex::Release).unwrap(), vec!["in_kbart".to_string(), "--in-ia".to_string()]);
+
+        let hit = json!({"ident": "aaaaaaaaaaaaarceaaaaaaaaai", "revision": "rev1"});
+        let entity = json!({
+            "state": "active",
+            "ident": "aaaaaaaaaaaaarceaaaaaaaaai",
+            "revision": "rev1",
+            "release_type": "article-journal",
+            "release_stage": "published",
+            "release_year": 2005,
+            "ext_ids": {"doi": "10.123/abc"},
+        });
+        assert_eq!(query.verify_hit(SearchIndex::Release, &hit, &entity), None);
+
+        let mut updated = entity.clone();
+        updated["revision"] = json!("rev2");
+        assert!(query.verify_hit(SearchIndex::Release, &hit, &updated).unwrap().contains("revision"));
+        let mut deleted = entity.clone();
+        deleted["state"] = json!("deleted");
+        assert!(query.verify_hit(SearchIndex::Release, &hit, &deleted).is_some());
+        let mut no_doi = entity.clone();
+        no_doi["ext_ids"] = json!({});
+        assert!(query.verify_hit(SearchIndex::Release, &hit, &no_doi).unwrap().contains("doi:*"));
+        let mut draft = entity.clone();
+        draft["release_stage"] = json!("draft");
+        assert!(query.verify_hit(SearchIndex::Release, &hit, &draft).unwrap().contains("stage:draft"));
+        let mut old = entity.clone();
+        old["release_year"] = json!(1999);
+        assert!(query.verify_hit(SearchIndex::Release, &hit, &old).unwrap().contains("1999"));
+
+        // file release_ids is a list
+        let file_query = SearchQuery { filters: vec![SearchFilter::from_str("release_ids:aaaaaaaaaaaaarceaaaaaaaaai").unwrap()], ..Default::default() };
+        let file_hit = json!({"revision": "rev1"});
+        let file = json!({"state": "active", "revision": "rev1", "release_ids": ["aaaaaaaaaaaaarceaaaaaaaaai", "aaaaaaaaaaaaarceaaaaaaaaam"]});
+        assert_eq!(file_query.verify_hit(SearchIndex::File, &file_hit, &file), None);
+        assert!(file_query.verify_hit(SearchIndex::File, &file_hit, &json!({"revision": "rev1", "release_ids": []})).is_some());
+
+        // hits without a revision can't be verified
+        assert!(file_query.verify_hit(SearchIndex::File, &json!({}), &file).unwrap().contains("can't verify"));
+    }
+}
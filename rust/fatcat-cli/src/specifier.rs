@@ -1,10 +1,11 @@
 
 use fatcat_openapi::ApiNoContext;
+use fatcat_openapi::models;
 use anyhow::{Result, anyhow, Context};
 use std::str::FromStr;
 use lazy_static::lazy_static;
 use regex::Regex;
-use crate::{ApiEntityModel, FatcatApiClient};
+use crate::{ApiEntityModel, EntityType, FatcatApiClient};
 
 
 #[derive(Debug, PartialEq, Clone)]
@@ -37,15 +38,22 @@ pub enum FileLookupKey {
 pub enum Specifier {
     Release(String),
     ReleaseLookup(ReleaseLookupKey, String),
+    ReleaseRevision(String),
     Work(String),
+    WorkRevision(String),
     Container(String),
     ContainerLookup(ContainerLookupKey, String),
+    ContainerRevision(String),
     Creator(String),
     CreatorLookup(CreatorLookupKey, String),
+    CreatorRevision(String),
     File(String),
     FileLookup(FileLookupKey, String),
+    FileRevision(String),
     FileSet(String),
+    FileSetRevision(String),
     WebCapture(String),
+    WebCaptureRevision(String),
     Editgroup(String),
     Editor(String),
     EditorUsername(String),
@@ -60,6 +68,8 @@ impl Specifier {
         use Specifier::*;
         match self {
             Release(_) | Work(_) | Creator(_) | Container(_) | File(_) | FileSet(_) | WebCapture(_) | Editgroup(_) | Editor(_) | Changelog(_) => Ok(self),
+            // revisions are immutable, and can't be resolved to a single entity ident
+            ReleaseRevision(..) | WorkRevision(..) | ContainerRevision(..) | CreatorRevision(..) | FileRevision(..) | FileSetRevision(..) | WebCaptureRevision(..) => Ok(self),
             ReleaseLookup(_, _) => Ok(self.get_from_api(api_client, None, None)?.specifier()),
             ContainerLookup(_, _) => Ok(self.get_from_api(api_client, None, None)?.specifier()),
             CreatorLookup(_, _) => Ok(self.get_from_api(api_client, None, None)?.specifier()),
@@ -70,6 +80,48 @@ impl Specifier {
         }
     }
 
+    /// Specifier for the entity of the given type with the given ident (FCID)
+    pub fn from_ident(entity_type: EntityType, ident: &str) -> Specifier {
+        match entity_type {
+            EntityType::Release => Specifier::Release(ident.to_string()),
+            EntityType::Work => Specifier::Work(ident.to_string()),
+            EntityType::Container => Specifier::Container(ident.to_string()),
+            EntityType::Creator => Specifier::Creator(ident.to_string()),
+            EntityType::File => Specifier::File(ident.to_string()),
+            EntityType::FileSet => Specifier::FileSet(ident.to_string()),
+            EntityType::WebCapture => Specifier::WebCapture(ident.to_string()),
+        }
+    }
+
+    /// Specifier for a specific revision of an entity of the given type
+    pub fn from_revision(entity_type: EntityType, rev_id: &str) -> Specifier {
+        match entity_type {
+            EntityType::Release => Specifier::ReleaseRevision(rev_id.to_string()),
+            EntityType::Work => Specifier::WorkRevision(rev_id.to_string()),
+            EntityType::Container => Specifier::ContainerRevision(rev_id.to_string()),
+            EntityType::Creator => Specifier::CreatorRevision(rev_id.to_string()),
+            EntityType::File => Specifier::FileRevision(rev_id.to_string()),
+            EntityType::FileSet => Specifier::FileSetRevision(rev_id.to_string()),
+            EntityType::WebCapture => Specifier::WebCaptureRevision(rev_id.to_string()),
+        }
+    }
+
+    /// Returns the entity type this specifier refers to, if it refers to a catalog entity (or
+    /// revision, or lookup) at all.
+    pub fn entity_type(&self) -> Option<EntityType> {
+        use Specifier::*;
+        match self {
+            Release(_) | ReleaseLookup(..) | ReleaseRevision(_) => Some(EntityType::Release),
+            Work(_) | WorkRevision(_) => Some(EntityType::Work),
+            Container(_) | ContainerLookup(..) | ContainerRevision(_) => Some(EntityType::Container),
+            Creator(_) | CreatorLookup(..) | CreatorRevision(_) => Some(EntityType::Creator),
+            File(_) | FileLookup(..) | FileRevision(_) => Some(EntityType::File),
+            FileSet(_) | FileSetRevision(_) => Some(EntityType::FileSet),
+            WebCapture(_) | WebCaptureRevision(_) => Some(EntityType::WebCapture),
+            Editgroup(_) | Editor(_) | EditorUsername(_) | Changelog(_) => None,
+        }
+    }
+
     pub fn get_from_api(&self, api_client: &mut FatcatApiClient, expand: Option<String>, hide: Option<String>) -> Result<Box<dyn ApiEntityModel>> {
         use Specifier::*;
         let ret: Result<Box<dyn ApiEntityModel>> = match self {
@@ -179,6 +231,55 @@ impl Specifier {
                     fatcat_openapi::GetWebcaptureResponse::NotFound(err) => Err(anyhow!("Not Found: {}", err.message)),
                     resp => Err(anyhow!("{:?}", resp)).with_context(|| format!("API GET failed: {:?}", self)),
                 },
+            ReleaseRevision(rev_id) =>
+                match api_client.rt.block_on(api_client.api.get_release_revision(rev_id.to_string(), expand, hide))? {
+                    fatcat_openapi::GetReleaseRevisionResponse::FoundEntityRevision(model) => Ok(Box::new(model)),
+                    fatcat_openapi::GetReleaseRevisionResponse::BadRequest(err) => Err(anyhow!("Bad Request ({}): {}", err.error, err.message)),
+                    fatcat_openapi::GetReleaseRevisionResponse::NotFound(err) => Err(anyhow!("Not Found: {}", err.message)),
+                    resp => Err(anyhow!("{:?}", resp)).with_context(|| format!("API GET failed: {:?}", self)),
+                },
+            WorkRevision(rev_id) =>
+                match api_client.rt.block_on(api_client.api.get_work_revision(rev_id.to_string(), expand, hide))? {
+                    fatcat_openapi::GetWorkRevisionResponse::FoundEntityRevision(model) => Ok(Box::new(model)),
+                    fatcat_openapi::GetWorkRevisionResponse::BadRequest(err) => Err(anyhow!("Bad Request ({}): {}", err.error, err.message)),
+                    fatcat_openapi::GetWorkRevisionResponse::NotFound(err) => Err(anyhow!("Not Found: {}", err.message)),
+                    resp => Err(anyhow!("{:?}", resp)).with_context(|| format!("API GET failed: {:?}", self)),
+                },
+            ContainerRevision(rev_id) =>
+                match api_client.rt.block_on(api_client.api.get_container_revision(rev_id.to_string(), expand, hide))? {
+                    fatcat_openapi::GetContainerRevisionResponse::FoundEntityRevision(model) => Ok(Box::new(model)),
+                    fatcat_openapi::GetContainerRevisionResponse::BadRequest(err) => Err(anyhow!("Bad Request ({}): {}", err.error, err.message)),
+                    fatcat_openapi::GetContainerRevisionResponse::NotFound(err) => Err(anyhow!("Not Found: {}", err.message)),
+                    resp => Err(anyhow!("{:?}", resp)).with_context(|| format!("API GET failed: {:?}", self)),
+                },
+            CreatorRevision(rev_id) =>
+                match api_client.rt.block_on(api_client.api.get_creator_revision(rev_id.to_string(), expand, hide))? {
+                    fatcat_openapi::GetCreatorRevisionResponse::FoundEntityRevision(model) => Ok(Box::new(model)),
+                    fatcat_openapi::GetCreatorRevisionResponse::BadRequest(err) => Err(anyhow!("Bad Request ({}): {}", err.error, err.message)),
+                    fatcat_openapi::GetCreatorRevisionResponse::NotFound(err) => Err(anyhow!("Not Found: {}", err.message)),
+                    resp => Err(anyhow!("{:?}", resp)).with_context(|| format!("API GET failed: {:?}", self)),
+                },
+            FileRevision(rev_id) =>
+                match api_client.rt.block_on(api_client.api.get_file_revision(rev_id.to_string(), expand, hide))? {
+                    fatcat_openapi::GetFileRevisionResponse::FoundEntityRevision(model) => Ok(Box::new(model)),
+                    fatcat_openapi::GetFileRevisionResponse::BadRequest(err) => Err(anyhow!("Bad Request ({}): {}", err.error, err.message)),
+                    fatcat_openapi::GetFileRevisionResponse::NotFound(err) => Err(anyhow!("Not Found: {}", err.message)),
+                    resp => Err(anyhow!("{:?}", resp)).with_context(|| format!("API GET failed: {:?}", self)),
+                },
+            FileSetRevision(rev_id) =>
+                match api_client.rt.block_on(api_client.api.get_fileset_revision(rev_id.to_string(), expand, hide))? {
+                    fatcat_openapi::GetFilesetRevisionResponse::FoundEntityRevision(model) => Ok(Box::new(model)),
+                    fatcat_openapi::GetFilesetRevisionResponse::BadRequest(err) => Err(anyhow!("Bad Request ({}): {}", err.error, err.message)),
+                    fatcat_openapi::GetFilesetRevisionResponse::NotFound(err) => Err(anyhow!("Not Found: {}", err.message)),
+                    resp => Err(anyhow!("{:?}", resp)).with_context(|| format!("API GET failed: {:?}", self)),
+                },
+            WebCaptureRevision(rev_id) =>
+                match api_client.rt.block_on(api_client.api.get_webcapture_revision(rev_id.to_string(), expand, hide))? {
+                    fatcat_openapi::GetWebcaptureRevisionResponse::FoundEntityRevision(model) => Ok(Box::new(model)),
+                    fatcat_openapi::GetWebcaptureRevisionResponse::BadRequest(err) => Err(anyhow!("Bad Request ({}): {}", err.error, err.message)),
+                    fatcat_openapi::GetWebcaptureRevisionResponse::NotFound(err) => Err(anyhow!("Not Found: {}", err.message)),
+                    resp => Err(anyhow!("{:?}", resp)).with_context(|| format!("API GET failed: {:?}", self)),
+                },
             Editgroup(fcid) =>
                 match api_client.rt.block_on(api_client.api.get_editgroup(fcid.to_string()))? {
                     fatcat_openapi::GetEditgroupResponse::Found(model) => Ok(Box::new(model)),
@@ -209,6 +310,72 @@ impl Specifier {
             Err(_) => ret.with_context(|| format!("Failed to GET {:?}", self)),
         }
     }
+
+    /// Fetches the edit history (changelog entries, editgroups, and edits) for an entity. Lookup
+    /// specifiers need to be resolved with `into_entity_specifier()` first.
+    pub fn get_history(&self, api_client: &mut FatcatApiClient, limit: Option<i64>) -> Result<Vec<models::EntityHistoryEntry>> {
+        use Specifier::*;
+        let ret: Result<Vec<models::EntityHistoryEntry>> = match self {
+            Release(fcid) =>
+                match api_client.rt.block_on(api_client.api.get_release_history(fcid.to_string(), limit))? {
+                    fatcat_openapi::GetReleaseHistoryResponse::FoundEntityHistory(entries) => Ok(entries),
+                    fatcat_openapi::GetReleaseHistoryResponse::BadRequest(err) => Err(anyhow!("Bad Request ({}): {}", err.error, err.message)),
+                    fatcat_openapi::GetReleaseHistoryResponse::NotFound(err) => Err(anyhow!("Not Found: {}", err.message)),
+                    resp => Err(anyhow!("{:?}", resp)).with_context(|| format!("API GET failed: {:?}", self)),
+                },
+            Work(fcid) =>
+                match api_client.rt.block_on(api_client.api.get_work_history(fcid.to_string(), limit))? {
+                    fatcat_openapi::GetWorkHistoryResponse::FoundEntityHistory(entries) => Ok(entries),
+                    fatcat_openapi::GetWorkHistoryResponse::BadRequest(err) => Err(anyhow!("Bad Request ({}): {}", err.error, err.message)),
+                    fatcat_openapi::GetWorkHistoryResponse::NotFound(err) => Err(anyhow!("Not Found: {}", err.message)),
+                    resp => Err(anyhow!("{:?}", resp)).with_context(|| format!("API GET failed: {:?}", self)),
+                },
+            Container(fcid) =>
+                match api_client.rt.block_on(api_client.api.get_container_history(fcid.to_string(), limit))? {
+                    fatcat_openapi::GetContainerHistoryResponse::FoundEntityHistory(entries) => Ok(entries),
+                    fatcat_openapi::GetContainerHistoryResponse::BadRequest(err) => Err(anyhow!("Bad Request ({}): {}", err.error, err.message)),
+                    fatcat_openapi::GetContainerHistoryResponse::NotFound(err) => Err(anyhow!("Not Found: {}", err.message)),
+                    resp => Err(anyhow!("{:?}", resp)).with_context(|| format!("API GET failed: {:?}", self)),
+                },
+            Creator(fcid) =>
+                match api_client.rt.block_on(api_client.api.get_creator_history(fcid.to_string(), limit))? {
+                    fatcat_openapi::GetCreatorHistoryResponse::FoundEntityHistory(entries) => Ok(entries),
+                    fatcat_openapi::GetCreatorHistoryResponse::BadRequest(err) => Err(anyhow!("Bad Request ({}): {}", err.error, err.message)),
+                    fatcat_openapi::GetCreatorHistoryResponse::NotFound(err) => Err(anyhow!("Not Found: {}", err.message)),
+                    resp => Err(anyhow!("{:?}", resp)).with_context(|| format!("API GET failed: {:?}", self)),
+                },
+            File(fcid) =>
+                match api_client.rt.block_on(api_client.api.get_file_history(fcid.to_string(), limit))? {
+                    fatcat_openapi::GetFileHistoryResponse::FoundEntityHistory(entries) => Ok(entries),
+                    fatcat_openapi::GetFileHistoryResponse::BadRequest(err) => Err(anyhow!("Bad Request ({}): {}", err.error, err.message)),
+                    fatcat_openapi::GetFileHistoryResponse::NotFound(err) => Err(anyhow!("Not Found: {}", err.message)),
+                    resp => Err(anyhow!("{:?}", resp)).with_context(|| format!("API GET failed: {:?}", self)),
+                },
+            FileSet(fcid) =>
+                match api_client.rt.block_on(api_client.api.get_fileset_history(fcid.to_string(), limit))? {
+                    fatcat_openapi::GetFilesetHistoryResponse::FoundEntityHistory(entries) => Ok(entries),
+                    fatcat_openapi::GetFilesetHistoryResponse::BadRequest(err) => Err(anyhow!("Bad Request ({}): {}", err.error, err.message)),
+                    fatcat_openapi::GetFilesetHistoryResponse::NotFound(err) => Err(anyhow!("Not Found: {}", err.message)),
+                    resp => Err(anyhow!("{:?}", resp)).with_context(|| format!("API GET failed: {:?}", self)),
+                },
+            WebCapture(fcid) =>
+                match api_client.rt.block_on(api_client.api.get_webcapture_history(fcid.to_string(), limit))? {
+                    fatcat_openapi::GetWebcaptureHistoryResponse::FoundEntityHistory(entries) => Ok(entries),
+                    fatcat_openapi::GetWebcaptureHistoryResponse::BadRequest(err) => Err(anyhow!("Bad Request ({}): {}", err.error, err.message)),
+                    fatcat_openapi::GetWebcaptureHistoryResponse::NotFound(err) => Err(anyhow!("Not Found: {}", err.message)),
+                    resp => Err(anyhow!("{:?}", resp)).with_context(|| format!("API GET failed: {:?}", self)),
+                },
+            Editgroup(..) | Editor(..) | Changelog(..) | ReleaseRevision(..) | WorkRevision(..) | ContainerRevision(..) | CreatorRevision(..) | FileRevision(..) | FileSetRevision(..) | WebCaptureRevision(..) => {
+                return Err(anyhow!("entity history not available for this specifier type: {:?}", self))
+            },
+            EditorUsername(..) | ReleaseLookup(..) | ContainerLookup(..) | FileLookup(..) | CreatorLookup(..) =>
+                return Err(anyhow!("into_entity_specifier() didn't work?")),
+        };
+        match ret {
+            Ok(_) => ret,
+            Err(_) => ret.with_context(|| format!("Failed to fetch history for {:?}", self)),
+        }
+    }
 }
 
 impl FromStr for Specifier {
@@ -234,6 +401,23 @@ impl FromStr for Specifier {
             };
         }
 
+        // then try revision prefixes
+        lazy_static! {
+            static ref SPEC_REVISION_RE: Regex = Regex::new(r"^(release|work|creator|container|file|fileset|webcapture)_rev:([0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12})$").unwrap();
+        }
+        if let Some(caps) = SPEC_REVISION_RE.captures(s) {
+            return match (&caps[1], &caps[2]) {
+                ("release", rev) => Ok(Specifier::ReleaseRevision(rev.to_string())),
+                ("work", rev) => Ok(Specifier::WorkRevision(rev.to_string())),
+                ("container", rev) => Ok(Specifier::ContainerRevision(rev.to_string())),
+                ("creator", rev) => Ok(Specifier::CreatorRevision(rev.to_string())),
+                ("file", rev) => Ok(Specifier::FileRevision(rev.to_string())),
+                ("fileset", rev) => Ok(Specifier::FileSetRevision(rev.to_string())),
+                ("webcapture", rev) => Ok(Specifier::WebCaptureRevision(rev.to_string())),
+                _ => Err(anyhow!("unexpected fatcat revision type: {}", &caps[1])),
+            };
+        }
+
         // then try lookup prefixes
         lazy_static! {
             static ref SPEC_LOOKUP_RE: Regex = Regex::new(r"^(doi|pmcid|pmid|arxiv|issnl|orcid|sha1|sha256|md5|username|changelog):(\S+)$").unwrap();
@@ -264,6 +448,88 @@ impl FromStr for Specifier {
     }
 }
 
+/// Reference to an editgroup, as passed on the command line.
+///
+/// Parses either an editgroup id (with or without the "editgroup_" prefix) or one of the keywords
+/// "new" (or "new:<description>"), "auto", and "last".
+#[derive(Debug, PartialEq, Clone)]
+pub enum EditgroupSpecifier {
+    Id(String),
+    /// Create a new editgroup, optionally with a description
+    New(Option<String>),
+    /// Most recent unsubmitted editgroup created by fatcat-cli, or a new one if there isn't one
+    Auto,
+    /// Most recently created editgroup of the authenticated editor, in any state
+    Last,
+}
+
+impl EditgroupSpecifier {
+
+    /// Returns the editgroup id, creating or looking up an editgroup if needed.
+    ///
+    /// `description` is used for any new editgroup, unless one was given as "new:<description>".
+    pub fn resolve(&self, api_client: &mut FatcatApiClient, description: Option<String>) -> Result<String> {
+        use EditgroupSpecifier::*;
+        let (eg, created) = match self {
+            Id(eg_id) => return Ok(eg_id.to_string()),
+            New(new_description) => (api_client.create_editgroup(new_description.clone().or(description))?, true),
+            Auto => match api_client.find_unsubmitted_editgroup()? {
+                Some(eg) => (eg, false),
+                None => (api_client.create_editgroup(description)?, true),
+            },
+            Last => {
+                let editor_id = match api_client.editor_id {
+                    Some(ref eid) => eid.clone(),
+                    None => return Err(anyhow!("require a working auth token to find the last editgroup")),
+                };
+                match api_client.get_editor_editgroups(editor_id, Some(10))?.into_iter().max_by_key(|eg| eg.created) {
+                    Some(eg) => (eg, false),
+                    None => return Err(anyhow!("no editgroups found for this editor")),
+                }
+            },
+        };
+        let eg_id = eg.editgroup_id.context("expected editgroup_id in API response")?;
+        if created {
+            eprintln!("Created new editgroup: editgroup_{}", eg_id);
+        }
+        Ok(eg_id)
+    }
+
+    /// Like `resolve()`, but never creates an editgroup, for commands which act on an editgroup
+    /// (like accepting it) and would be pointless on a new, empty one.
+    pub fn resolve_existing(&self, api_client: &mut FatcatApiClient) -> Result<String> {
+        use EditgroupSpecifier::*;
+        match self {
+            New(_) => Err(anyhow!("'new' would create an empty editgroup; expected an existing editgroup")),
+            Auto => match api_client.find_unsubmitted_editgroup()? {
+                Some(eg) => eg.editgroup_id.context("expected editgroup_id in API response"),
+                None => Err(anyhow!("no unsubmitted editgroup found")),
+            },
+            Id(_) | Last => self.resolve(api_client, None),
+        }
+    }
+}
+
+impl FromStr for EditgroupSpecifier {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        lazy_static! {
+            static ref EDITGROUP_ID_RE: Regex = Regex::new(r"^(editgroup_)?([2-7a-z]{26})$").unwrap();
+        }
+        if let Some(caps) = EDITGROUP_ID_RE.captures(s) {
+            return Ok(EditgroupSpecifier::Id(caps[2].to_string()));
+        }
+        match s {
+            "new" => Ok(EditgroupSpecifier::New(None)),
+            "auto" => Ok(EditgroupSpecifier::Auto),
+            "last" => Ok(EditgroupSpecifier::Last),
+            _ if s.starts_with("new:") && s.len() > 4 => Ok(EditgroupSpecifier::New(Some(s[4..].to_string()))),
+            _ => Err(anyhow!("expecting an editgroup id, or one of 'new', 'auto', 'last': {}", s)),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -277,6 +543,24 @@ mod tests {
         assert!(Specifier::from_str("doi:").is_err());
         assert_eq!(Specifier::from_str("changelog_1234").unwrap(), Specifier::Changelog(1234));
         assert!(Specifier::from_str("changelog_12E4").is_err());
+        assert_eq!(Specifier::from_str("release_rev:0a5bd3d8-3ee3-4b5f-9b1c-1e5ebd1d4c2b").unwrap(), Specifier::ReleaseRevision("0a5bd3d8-3ee3-4b5f-9b1c-1e5ebd1d4c2b".to_string()));
+        assert_eq!(Specifier::from_str("fileset_rev:0a5bd3d8-3ee3-4b5f-9b1c-1e5ebd1d4c2b").unwrap(), Specifier::FileSetRevision("0a5bd3d8-3ee3-4b5f-9b1c-1e5ebd1d4c2b".to_string()));
+        assert!(Specifier::from_str("release_rev:0a5bd3d8").is_err());
+        assert!(Specifier::from_str("editgroup_rev:0a5bd3d8-3ee3-4b5f-9b1c-1e5ebd1d4c2b").is_err());
+    }
+
+    #[test]
+    fn test_editgroup_specifier_from_str() -> () {
+        assert_eq!(EditgroupSpecifier::from_str("aaaaaaaaaaaabo53aaaaaaaaae").unwrap(), EditgroupSpecifier::Id("aaaaaaaaaaaabo53aaaaaaaaae".to_string()));
+        assert_eq!(EditgroupSpecifier::from_str("editgroup_aaaaaaaaaaaabo53aaaaaaaaae").unwrap(), EditgroupSpecifier::Id("aaaaaaaaaaaabo53aaaaaaaaae".to_string()));
+        assert_eq!(EditgroupSpecifier::from_str("new").unwrap(), EditgroupSpecifier::New(None));
+        assert_eq!(EditgroupSpecifier::from_str("new:fix some titles").unwrap(), EditgroupSpecifier::New(Some("fix some titles".to_string())));
+        assert_eq!(EditgroupSpecifier::from_str("auto").unwrap(), EditgroupSpecifier::Auto);
+        assert_eq!(EditgroupSpecifier::from_str("last").unwrap(), EditgroupSpecifier::Last);
+        assert!(EditgroupSpecifier::from_str("new:").is_err());
+        assert!(EditgroupSpecifier::from_str("release_aaaaaaaaaaaabo53aaaaaaaaae").is_err());
+        assert!(EditgroupSpecifier::from_str("editgroup_asdf").is_err());
+        assert!(EditgroupSpecifier::from_str("").is_err());
     }
 
 }
@@ -0,0 +1,95 @@
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use anyhow::{Result, anyhow, Context};
+
+
+/// Directory for fatcat-cli configuration and state files.
+///
+/// Follows the XDG convention: `$XDG_CONFIG_HOME/fatcat-cli`, falling back to
+/// `$HOME/.config/fatcat-cli`.
+pub fn config_dir() -> Result<PathBuf> {
+    let base = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => match std::env::var_os("HOME") {
+            Some(home) if !home.is_empty() => PathBuf::from(home).join(".config"),
+            _ => return Err(anyhow!("can't determine config directory (neither XDG_CONFIG_HOME nor HOME set)")),
+        },
+    };
+    Ok(base.join("fatcat-cli"))
+}
+
+/// Local session state which persists between invocations, like the current editgroup.
+///
+/// Editgroups only exist on a single API host, so state is keyed by host.
+#[derive(Debug, Default, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct CliState {
+    #[serde(default)]
+    pub current_editgroup: BTreeMap<String, String>,
+}
+
+impl CliState {
+
+    pub fn path() -> Result<PathBuf> {
+        Ok(config_dir()?.join("state.toml"))
+    }
+
+    /// Reads state from disk; a missing state file is the same as empty state.
+    pub fn load() -> Result<Self> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let raw = std::fs::read_to_string(&path)
+            .with_context(|| format!("reading state file {:?}", path))?;
+        toml::from_str(&raw).with_context(|| format!("parsing state file {:?}", path))
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path()?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).with_context(|| format!("creating config directory {:?}", dir))?;
+        }
+        std::fs::write(&path, toml::to_string(self)?)
+            .with_context(|| format!("writing state file {:?}", path))
+    }
+
+    pub fn current_editgroup(&self, api_host: &str) -> Option<String> {
+        self.current_editgroup.get(host_key(api_host)).cloned()
+    }
+
+    pub fn set_current_editgroup(&mut self, api_host: &str, editgroup_id: Option<String>) {
+        match editgroup_id {
+            Some(eg_id) => self.current_editgroup.insert(host_key(api_host).to_string(), eg_id),
+            None => self.current_editgroup.remove(host_key(api_host)),
+        };
+    }
+}
+
+fn host_key(api_host: &str) -> &str {
+    api_host.trim_end_matches('/')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_current_editgroup() -> () {
+        let mut state = CliState::default();
+        assert_eq!(state.current_editgroup("https://api.fatcat.wiki"), None);
+        state.set_current_editgroup("https://api.fatcat.wiki/", Some("aaaaaaaaaaaabo53aaaaaaaaae".to_string()));
+        state.set_current_editgroup("http://localhost:9411", Some("aaaaaaaaaaaabo53aaaaaaaaaq".to_string()));
+        assert_eq!(state.current_editgroup("https://api.fatcat.wiki"), Some("aaaaaaaaaaaabo53aaaaaaaaae".to_string()));
+
+        let roundtrip: CliState = toml::from_str(&toml::to_string(&state).unwrap()).unwrap();
+        assert_eq!(roundtrip, state);
+
+        state.set_current_editgroup("https://api.fatcat.wiki", None);
+        assert_eq!(state.current_editgroup("https://api.fatcat.wiki"), None);
+        assert_eq!(state.current_editgroup("http://localhost:9411"), Some("aaaaaaaaaaaabo53aaaaaaaaaq".to_string()));
+
+        let empty: CliState = toml::from_str("").unwrap();
+        assert_eq!(empty, CliState::default());
+    }
+}